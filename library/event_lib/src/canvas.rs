@@ -1,6 +1,29 @@
 pub mod canvas {
-    use druid::{im::HashMap, piet::ImageFormat, ImageBuf, Data};
-    use std::collections::{HashSet, VecDeque};
+    use druid::{
+        im::{HashMap, Vector},
+        kurbo::{BezPath, Circle, Ellipse, Line, Rect},
+        piet::{
+            Device, FontFamily, FontWeight, ImageFormat, LineCap, LineJoin, RenderContext,
+            StrokeStyle, Text, TextLayout, TextLayoutBuilder,
+        },
+        Affine, Color, Data, ImageBuf, Size,
+    };
+    use std::{collections::VecDeque, sync::Arc};
+
+    /// Font size of the texts, in pixels of the image, used until the user changes it
+    pub const DEFAULT_FONT_SIZE: f64 = 24.0;
+    /// Space between a text and the border of its box, relative to the font size
+    const TEXT_PADDING_RATIO: f64 = 0.25;
+    /// Coverage of the pixels of the image by a shape, from 0 to 1,
+    /// the pixels that are not in the map are not covered
+    pub type Coverage = std::collections::HashMap<(i32, i32), f64>;
+
+    /// Opacity of the highlighter, 40%
+    const HIGHLIGHTER_ALPHA: u32 = 0x66;
+    /// The highlighter is wider than the other strokes of the same thickness
+    pub const HIGHLIGHTER_WIDTH_RATIO: usize = 4;
+    /// Side of the square painted by the redaction brush, as a multiple of the thickness
+    pub const REDACTION_BRUSH_RATIO: usize = 4;
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
     pub enum Shape {
@@ -13,6 +36,79 @@ pub mod canvas {
         None,
         Fill,
         Color(u32),
+        Select,
+        Text,
+        Arrow,
+        Highlighter,
+        Redact,
+        Ellipse,
+        Bucket,
+        Eyedropper,
+        Step,
+    }
+
+    /// Neighbours of a pixel reached by the flood fill
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+    pub enum Connectivity {
+        /// Only the pixels sharing an edge
+        Four,
+        /// Also the pixels sharing a corner
+        Eight,
+    }
+
+    /// How the pixels of a redacted area are hidden
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+    pub enum RedactionMode {
+        Pixelate,
+        Blur,
+        Solid,
+    }
+
+    /// Ends of an arrow that have a head
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+    pub enum ArrowHeads {
+        None,
+        End,
+        Both,
+    }
+
+    /// Pixels painted by a flood fill, with the image before the fill.
+    /// A fill can cover the whole image, so its pixels are kept in a mask
+    /// instead of `modified_pixel`, and their colours are read from `original`
+    #[derive(Debug, Clone)]
+    pub struct FillLayer {
+        /// Whether every pixel of `original` has been filled and not restored yet
+        filled: Arc<Vec<bool>>,
+        original: ImageBuf,
+        /// Position of the current image inside `original`, it moves when the image is cropped
+        offset: (usize, usize),
+    }
+
+    impl FillLayer {
+        /// Return the index in the mask of the pixel (x, y) of the current image
+        fn index(&self, x: usize, y: usize) -> Option<usize> {
+            let (x, y) = (x + self.offset.0, y + self.offset.1);
+            if x >= self.original.width() || y >= self.original.height() {
+                return None;
+            }
+
+            return Some(y * self.original.width() + x);
+        }
+
+        /// Return the colour of the pixel (x, y) before the fill, if it has been filled
+        fn original_color(&self, x: usize, y: usize) -> Option<u32> {
+            let i = self.index(x, y).filter(|i| self.filled[*i])?;
+            let pixel = &self.original.raw_pixels()[i * 4..i * 4 + 4];
+
+            return Some(u32::from_be_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]));
+        }
+
+        /// Forget the pixel (x, y), the mask is copied only if the pixel is filled
+        fn forget(&mut self, x: usize, y: usize) {
+            if let Some(i) = self.index(x, y).filter(|i| self.filled[*i]) {
+                Arc::make_mut(&mut self.filled)[i] = false;
+            }
+        }
     }
 
     #[derive(Debug, Clone, Data)]
@@ -24,10 +120,35 @@ pub mod canvas {
         fill: bool,
         #[data(ignore)]
         pub modified_pixel: HashMap<(usize, usize), u32>,
+        /// Pixels painted by the flood fills, the oldest fill first
+        #[data(ignore)]
+        pub fills: Vector<FillLayer>,
         #[data(ignore)]
         pub buf_point: VecDeque<(usize, usize)>,
         #[data(ignore)]
         pub start_point_cut: (usize, usize),
+        /// Pixels already drawn by the current stroke, with their colour before the stroke
+        /// and the coverage drawn, so that overlapping segments of a stroke don't get darker
+        #[data(ignore)]
+        stroke: std::collections::HashMap<(usize, usize), (u32, f64)>,
+        /// Shapes kept as objects over the image, the last one is on top
+        pub annotations: Vector<Annotation>,
+        selected: Option<usize>,
+        /// Text annotation receiving the keys typed by the user
+        editing: Option<usize>,
+        font_size: f64,
+        text_background: bool,
+        arrow_heads: ArrowHeads,
+        redaction_mode: RedactionMode,
+        /// Size of the blocks of the pixelation or radius of the blur, in pixels of the image
+        redaction_strength: f64,
+        /// The redaction is painted with a brush instead of a dragged rectangle
+        redaction_brush: bool,
+        /// Highest difference of a channel from the clicked pixel for a pixel to be filled
+        fill_tolerance: f64,
+        connectivity: Connectivity,
+        /// Number of the next step marker placed
+        next_step: u32,
     }
 
     impl Canvas {
@@ -37,14 +158,33 @@ pub mod canvas {
                 color: 0xff0000ff,
                 fill: false,
                 modified_pixel: HashMap::new(),
+                fills: Vector::new(),
                 buf_point: VecDeque::new(),
                 start_point_cut: (0, 0),
+                stroke: std::collections::HashMap::new(),
+                annotations: Vector::new(),
+                selected: None,
+                editing: None,
+                font_size: DEFAULT_FONT_SIZE,
+                text_background: false,
+                arrow_heads: ArrowHeads::End,
+                redaction_mode: RedactionMode::Pixelate,
+                redaction_strength: 12.0,
+                redaction_brush: false,
+                fill_tolerance: 32.0,
+                connectivity: Connectivity::Four,
+                next_step: 1,
             };
         }
 
         pub fn set_shape(&mut self, new_shape: Shape) {
             self.shape = new_shape;
             self.buf_point.clear();
+            self.finish_editing();
+
+            if new_shape != Shape::Select {
+                self.selected = None;
+            }
         }
 
         pub fn get_shape(&self) -> Shape {
@@ -67,62 +207,524 @@ pub mod canvas {
             return self.fill;
         }
 
-        pub fn draw_shape(
+        /// Draw a segment of a free hand stroke with the current colour
+        pub fn draw_stroke(
             &mut self,
-            mut pixels: Vec<u8>,
+            pixels: Vec<u8>,
             width: usize,
             height: usize,
             start: (usize, usize),
             end: (usize, usize),
-            shape: Shape,
             thickness: usize,
         ) -> ImageBuf {
-            let filled_pixels = match shape {
-                Shape::Line => generate_line_coordinates(
-                    (start.0 as f32, start.1 as f32),
-                    (end.0 as f32, end.1 as f32),
-                    thickness,
-                ),
-                Shape::Cirle => {
-                    if self.fill {
-                        generate_fill_circle_coordinates(start, end)
-                    } else {
-                        generate_empty_circle_coordinates(start, end, thickness)
-                    }
-                }
-                Shape::Rectangle => {
-                    if self.fill {
-                        generate_fill_rectangle_coordinates(start, end)
-                    } else {
-                        generate_empty_rectangle_coordinates(start, end, thickness)
+            let color = self.color;
+
+            return self.blend_stroke(pixels, (width, height), start, end, thickness as f64, color);
+        }
+
+        pub fn get_arrow_heads(&self) -> ArrowHeads {
+            return self.arrow_heads;
+        }
+
+        pub fn set_arrow_heads(&mut self, heads: ArrowHeads) {
+            self.arrow_heads = heads;
+        }
+
+        pub fn get_redaction_mode(&self) -> RedactionMode {
+            return self.redaction_mode;
+        }
+
+        pub fn set_redaction_mode(&mut self, mode: RedactionMode) {
+            self.redaction_mode = mode;
+        }
+
+        pub fn get_redaction_strength(&self) -> f64 {
+            return self.redaction_strength;
+        }
+
+        pub fn set_redaction_strength(&mut self, strength: f64) {
+            self.redaction_strength = strength;
+        }
+
+        pub fn get_redaction_brush(&self) -> bool {
+            return self.redaction_brush;
+        }
+
+        pub fn set_redaction_brush(&mut self, brush: bool) {
+            self.redaction_brush = brush;
+        }
+
+        /// Hide the pixels of the areas, given as (start, end) corners, with the current
+        /// redaction mode. The original colours recorded for the rubber are forgotten
+        /// in the areas, so that the redacted content can't be restored
+        pub fn redact(
+            &mut self,
+            mut pixels: Vec<u8>,
+            width: usize,
+            height: usize,
+            areas: &[((usize, usize), (usize, usize))],
+        ) -> ImageBuf {
+            for (start, end) in areas {
+                if let Some((x0, y0, x1, y1)) =
+                    self.redact_pixels(&mut pixels, width, height, *start, *end)
+                {
+                    for y in y0..y1 {
+                        for x in x0..x1 {
+                            self.forget_pixel(x, y, width);
+                        }
                     }
                 }
-                _ => panic!("Unable to draw a shape"),
+            }
+
+            return ImageBuf::from_raw(pixels, ImageFormat::RgbaSeparate, width, height);
+        }
+
+        /// Return the square areas painted by the redaction brush from `from` to `to`.
+        /// With the pixelation the areas follow the grid of the blocks, so that
+        /// the blocks of overlapping areas match
+        pub fn brush_areas(
+            &self,
+            from: (i32, i32),
+            to: (i32, i32),
+            thickness: usize,
+        ) -> Vec<((i32, i32), (i32, i32))> {
+            let size = (thickness.max(1) * REDACTION_BRUSH_RATIO) as f64;
+            let block = match self.redaction_mode {
+                RedactionMode::Pixelate => (self.redaction_strength as usize).max(2) as f64,
+                _ => 1.0,
             };
 
-            for (x, y) in filled_pixels {
-                if x < width && y < height {
-                    let true_x = x * ImageFormat::RgbaSeparate.bytes_per_pixel();
-                    let true_y = y * width * ImageFormat::RgbaSeparate.bytes_per_pixel();
+            let (dx, dy) = ((to.0 - from.0) as f64, (to.1 - from.1) as f64);
+            let steps = ((dx * dx + dy * dy).sqrt() / (size / 4.0)).ceil().max(1.0) as usize;
 
-                    let mask = 0xff000000;
-                    let mut old_color: u32 = 0;
-                    for i in 0..ImageFormat::RgbaSeparate.bytes_per_pixel() {
-                        old_color = (old_color << 8) | pixels[true_x + true_y + i] as u32;
-                        pixels[true_x + true_y + i] = ((self.color & (mask >> i * 8))
-                            >> 8 * (ImageFormat::RgbaSeparate.bytes_per_pixel() - i - 1))
-                            as u8;
-                    }
+            return (0..=steps)
+                .map(|i| {
+                    let t = i as f64 / steps as f64;
+                    let (cx, cy) = (from.0 as f64 + dx * t, from.1 as f64 + dy * t);
+                    let x0 = ((cx - size / 2.0).max(0.0) / block).floor() * block;
+                    let y0 = ((cy - size / 2.0).max(0.0) / block).floor() * block;
+                    let x1 = ((cx + size / 2.0).max(0.0) / block).ceil() * block;
+                    let y1 = ((cy + size / 2.0).max(0.0) / block).ceil() * block;
+
+                    ((x0 as i32, y0 as i32), (x1 as i32, y1 as i32))
+                })
+                .collect();
+        }
+
+        /// Hide the pixels of the area between `start` and `end` with the current redaction mode.
+        /// Return the area redacted as (x0, y0, x1, y1), `None` if it is outside of the image
+        pub fn redact_pixels(
+            &self,
+            pixels: &mut [u8],
+            width: usize,
+            height: usize,
+            start: (usize, usize),
+            end: (usize, usize),
+        ) -> Option<(usize, usize, usize, usize)> {
+            let (x0, x1) = (start.0.min(end.0), start.0.max(end.0).min(width));
+            let (y0, y1) = (start.1.min(end.1), start.1.max(end.1).min(height));
+
+            if x0 >= x1 || y0 >= y1 {
+                return None;
+            }
+
+            let area = (x0, y0, x1, y1);
+            let strength = self.redaction_strength.max(1.0);
+
+            match self.redaction_mode {
+                RedactionMode::Pixelate => pixelate_area(pixels, width, area, strength as usize),
+                RedactionMode::Blur => blur_area(pixels, width, height, area, strength),
+                RedactionMode::Solid => fill_area(pixels, width, area, 0x000000ff),
+            }
+
+            return Some(area);
+        }
+
+        pub fn get_fill_tolerance(&self) -> f64 {
+            return self.fill_tolerance;
+        }
+
+        pub fn set_fill_tolerance(&mut self, tolerance: f64) {
+            self.fill_tolerance = tolerance;
+        }
+
+        pub fn get_connectivity(&self) -> Connectivity {
+            return self.connectivity;
+        }
+
+        pub fn set_connectivity(&mut self, connectivity: Connectivity) {
+            self.connectivity = connectivity;
+        }
+
+        /// Fill with the current colour the area of similar colour around `point`.
+        /// The filled pixels are recorded in a new `FillLayer`, so that the rubber can restore them
+        pub fn flood_fill(&mut self, buf: &ImageBuf, point: (usize, usize)) -> Option<ImageBuf> {
+            let (width, height) = (buf.width(), buf.height());
+            if point.0 >= width || point.1 >= height {
+                return None;
+            }
+
+            let mut pixels = buf.raw_pixels().to_vec();
+            let filled = flood_fill_area(
+                &pixels,
+                (width, height),
+                point,
+                self.fill_tolerance,
+                self.connectivity,
+            );
+
+            for (i, _) in filled.iter().enumerate().filter(|(_, filled)| **filled) {
+                blend_color(
+                    &mut pixels,
+                    i * ImageFormat::RgbaSeparate.bytes_per_pixel(),
+                    self.color,
+                    1.0,
+                );
+            }
+
+            self.fills.push_back(FillLayer {
+                filled: Arc::new(filled),
+                original: buf.clone(),
+                offset: (0, 0),
+            });
+
+            return Some(ImageBuf::from_raw(
+                pixels,
+                ImageFormat::RgbaSeparate,
+                width,
+                height,
+            ));
+        }
+
+        /// Return the colour of the pixel (x, y) before it was modified for the first time,
+        /// `None` if it has not been modified. `width` is the width of the image
+        pub fn original_color(&self, x: usize, y: usize, width: usize) -> Option<u32> {
+            let true_x = x * ImageFormat::RgbaSeparate.bytes_per_pixel();
+            let true_y = y * width * ImageFormat::RgbaSeparate.bytes_per_pixel();
+
+            // a pixel drawn before a fill is in `modified_pixel`, otherwise the oldest fill has its colour
+            if let Some(color) = self.modified_pixel.get(&(true_x, true_y)) {
+                return Some(*color);
+            }
+
+            return self.fills.iter().find_map(|fill| fill.original_color(x, y));
+        }
+
+        /// Forget the original colour of the pixel (x, y), so that it can't be restored anymore
+        pub fn forget_pixel(&mut self, x: usize, y: usize, width: usize) {
+            let true_x = x * ImageFormat::RgbaSeparate.bytes_per_pixel();
+            let true_y = y * width * ImageFormat::RgbaSeparate.bytes_per_pixel();
+
+            self.modified_pixel.remove(&(true_x, true_y));
+            for fill in self.fills.iter_mut() {
+                fill.forget(x, y);
+            }
+        }
+
+        pub fn has_modified_pixels(&self) -> bool {
+            return !self.modified_pixel.is_empty() || !self.fills.is_empty();
+        }
+
+        /// Forget the original colour of every pixel, used when a new image is loaded
+        pub fn clear_modified_pixels(&mut self) {
+            self.modified_pixel.clear();
+            self.fills.clear();
+        }
+
+        /// Move the pixels painted by the fills, used when the image is cropped from `offset`
+        pub fn crop_fills(&mut self, offset: (usize, usize)) {
+            for fill in self.fills.iter_mut() {
+                fill.offset = (fill.offset.0 + offset.0, fill.offset.1 + offset.1);
+            }
+        }
+
+        pub fn get_next_step(&self) -> u32 {
+            return self.next_step;
+        }
+
+        /// Start numbering the next step markers from `number`
+        pub fn set_next_step(&mut self, number: u32) {
+            self.next_step = number.max(1);
+        }
+
+        /// Number the next step marker from one again
+        pub fn reset_steps(&mut self) {
+            self.next_step = 1;
+        }
 
-                    if !self.modified_pixel.contains_key(&(true_x, true_y)) {
-                        self.modified_pixel.insert((true_x, true_y), old_color);
+        /// Create a step marker centered on `center`, with the next number
+        pub fn new_step(&mut self, center: (i32, i32), thickness: usize) -> Annotation {
+            let annotation = Annotation::step(center, self.color, thickness, self.next_step);
+            self.next_step += 1;
+
+            return annotation;
+        }
+
+        /// Create an annotation of the current shape, with the current colour and options
+        pub fn new_annotation(
+            &self,
+            start: (i32, i32),
+            end: (i32, i32),
+            thickness: usize,
+        ) -> Annotation {
+            let mut annotation =
+                Annotation::new(self.shape, start, end, self.color, thickness, self.fill);
+            annotation.heads = self.arrow_heads;
+
+            return annotation;
+        }
+
+        pub fn get_font_size(&self) -> f64 {
+            return self.font_size;
+        }
+
+        /// Set the font size of the next texts and of the text being edited
+        pub fn set_font_size(&mut self, font_size: f64) {
+            self.font_size = font_size;
+
+            if let Some(annotation) = self.get_editing_annotation_mut() {
+                annotation.font_size = font_size;
+                annotation.update_text_size();
+            }
+        }
+
+        pub fn get_text_background(&self) -> bool {
+            return self.text_background;
+        }
+
+        /// Set if the next texts and the text being edited have a box behind them
+        pub fn set_text_background(&mut self, background: bool) {
+            self.text_background = background;
+
+            if let Some(annotation) = self.get_editing_annotation_mut() {
+                annotation.background = background;
+            }
+        }
+
+        pub fn get_editing(&self) -> Option<usize> {
+            return self.editing;
+        }
+
+        fn get_editing_annotation_mut(&mut self) -> Option<&mut Annotation> {
+            return self.annotations.get_mut(self.editing?);
+        }
+
+        /// Send the keys typed by the user to the text annotation at `index`
+        pub fn start_editing(&mut self, index: usize) {
+            if index < self.annotations.len() && self.annotations[index].shape == Shape::Text {
+                self.editing = Some(index);
+                self.selected = Some(index);
+            }
+        }
+
+        /// Stop editing the text, it is removed if it has been left empty
+        pub fn finish_editing(&mut self) {
+            if let Some(index) = self.editing.take() {
+                if index < self.annotations.len() && self.annotations[index].text.is_empty() {
+                    self.annotations.remove(index);
+                }
+                self.selected = None;
+            }
+        }
+
+        pub fn insert_text(&mut self, text: &str) {
+            if let Some(annotation) = self.get_editing_annotation_mut() {
+                annotation.text.push_str(text);
+                annotation.update_text_size();
+            }
+        }
+
+        pub fn delete_last_char(&mut self) {
+            if let Some(annotation) = self.get_editing_annotation_mut() {
+                annotation.text.pop();
+                annotation.update_text_size();
+            }
+        }
+
+        pub fn add_annotation(&mut self, annotation: Annotation) {
+            self.annotations.push_back(annotation);
+        }
+
+        /// Remove all the annotations, they can not be moved on a new image
+        pub fn clear_annotations(&mut self) {
+            self.annotations.clear();
+            self.selected = None;
+            self.editing = None;
+        }
+
+        /// Remove the annotations and the modified pixels when the initial image is restored,
+        /// the settings of the tools (colour, font, redaction, fill, step number) are kept
+        pub fn reset_edits(&mut self) {
+            self.clear_annotations();
+            self.clear_modified_pixels();
+            self.buf_point.clear();
+            self.stroke.clear();
+            self.start_point_cut = (0, 0);
+        }
+
+        pub fn get_selected(&self) -> Option<usize> {
+            return self.selected;
+        }
+
+        pub fn select(&mut self, index: Option<usize>) {
+            self.selected = index.filter(|i| *i < self.annotations.len());
+        }
+
+        pub fn get_selected_annotation(&self) -> Option<&Annotation> {
+            return self.annotations.get(self.selected?);
+        }
+
+        pub fn get_selected_annotation_mut(&mut self) -> Option<&mut Annotation> {
+            return self.annotations.get_mut(self.selected?);
+        }
+
+        /// Return the index of the topmost annotation under `point`
+        pub fn annotation_at(&self, point: (i32, i32), tolerance: f64) -> Option<usize> {
+            return (0..self.annotations.len())
+                .rev()
+                .find(|i| self.annotations[*i].hit_test(point, tolerance));
+        }
+
+        /// Return the index of the handle of the selected annotation under `point`
+        pub fn handle_at(&self, point: (i32, i32), tolerance: f64) -> Option<usize> {
+            return self
+                .get_selected_annotation()?
+                .handles()
+                .iter()
+                .position(|handle| {
+                    (handle.0 - point.0).abs() as f64 <= tolerance
+                        && (handle.1 - point.1).abs() as f64 <= tolerance
+                });
+        }
+
+        pub fn delete_selected(&mut self) {
+            self.editing = None;
+
+            if let Some(index) = self.selected {
+                let removed = self.annotations.remove(index);
+                self.selected = None;
+
+                // the following steps take the place of the deleted one
+                if removed.shape == Shape::Step {
+                    for annotation in self.annotations.iter_mut() {
+                        if annotation.shape == Shape::Step && annotation.number > removed.number {
+                            annotation.number -= 1;
+                        }
+                    }
+                    if self.next_step > removed.number {
+                        self.next_step -= 1;
                     }
                 }
             }
+        }
+
+        /// Move the selected annotation over all the others
+        pub fn bring_to_front(&mut self) {
+            self.editing = None;
+
+            if let Some(index) = self.selected {
+                let annotation = self.annotations.remove(index);
+                self.annotations.push_back(annotation);
+                self.selected = Some(self.annotations.len() - 1);
+            }
+        }
+
+        /// Move the selected annotation under all the others
+        pub fn send_to_back(&mut self) {
+            self.editing = None;
+
+            if let Some(index) = self.selected {
+                let annotation = self.annotations.remove(index);
+                self.annotations.push_front(annotation);
+                self.selected = Some(0);
+            }
+        }
+
+        /// Move all the annotations, used when the image is cropped
+        pub fn translate_annotations(&mut self, dx: i32, dy: i32) {
+            for annotation in self.annotations.iter_mut() {
+                annotation.translate(dx, dy);
+            }
+        }
 
-            let img = ImageBuf::from_raw(pixels, ImageFormat::RgbaSeparate, width, height);
+        /// Start a new stroke of the free hand or highlighter tools
+        pub fn start_stroke(&mut self) {
+            self.stroke.clear();
+        }
+
+        /// Draw a segment of a stroke of the highlighter, blending the current colour
+        /// at 40% opacity over the image
+        pub fn draw_highlight(
+            &mut self,
+            pixels: Vec<u8>,
+            width: usize,
+            height: usize,
+            start: (usize, usize),
+            end: (usize, usize),
+            thickness: usize,
+        ) -> ImageBuf {
+            let alpha = (self.color & 0xff).min(HIGHLIGHTER_ALPHA);
+            let color = (self.color & 0xffffff00) | alpha;
+            let stroke_width = (thickness * HIGHLIGHTER_WIDTH_RATIO) as f64;
 
-            return img;
+            return self.blend_stroke(pixels, (width, height), start, end, stroke_width, color);
+        }
+
+        /// Blend a segment of the current stroke over the image. A pixel already drawn by
+        /// the stroke is drawn again from its colour before the stroke, with the highest
+        /// of the two coverages, so the stroke looks the same as if drawn at once
+        fn blend_stroke(
+            &mut self,
+            mut pixels: Vec<u8>,
+            size: (usize, usize),
+            start: (usize, usize),
+            end: (usize, usize),
+            stroke_width: f64,
+            color: u32,
+        ) -> ImageBuf {
+            let mut coverage = Coverage::new();
+            stroke_segment(
+                &mut coverage,
+                pixel_center(start.0 as f64, start.1 as f64),
+                pixel_center(end.0 as f64, end.1 as f64),
+                stroke_width,
+            );
+
+            for ((x, y), pixel_coverage) in coverage {
+                if x < 0 || y < 0 || x as usize >= size.0 || y as usize >= size.1 {
+                    continue;
+                }
+
+                let (x, y) = (x as usize, y as usize);
+                let true_x = x * ImageFormat::RgbaSeparate.bytes_per_pixel();
+                let true_y = y * size.0 * ImageFormat::RgbaSeparate.bytes_per_pixel();
+                let index = true_x + true_y;
+                let current_color = u32::from_be_bytes([
+                    pixels[index],
+                    pixels[index + 1],
+                    pixels[index + 2],
+                    pixels[index + 3],
+                ]);
+
+                let (before_stroke, drawn) = self
+                    .stroke
+                    .get(&(x, y))
+                    .copied()
+                    .unwrap_or((current_color, 0.0));
+                if pixel_coverage <= drawn {
+                    continue;
+                }
+
+                if self.original_color(x, y, size.0).is_none() {
+                    self.modified_pixel.insert((true_x, true_y), before_stroke);
+                }
+
+                pixels[index..index + 4].copy_from_slice(&before_stroke.to_be_bytes());
+                blend_color(&mut pixels, index, color, pixel_coverage);
+                self.stroke.insert((x, y), (before_stroke, pixel_coverage));
+            }
+
+            return ImageBuf::from_raw(pixels, ImageFormat::RgbaSeparate, size.0, size.1);
         }
 
         pub fn clear_pixel(
@@ -134,27 +736,24 @@ pub mod canvas {
             end: (usize, usize),
             thickness: usize,
         ) -> Option<Vec<u8>> {
-            let cleared_pixels = generate_line_coordinates(
-                (start.0 as f32, start.1 as f32),
-                (end.0 as f32, end.1 as f32),
-                thickness + 24,
+            let mut cleared_pixels = Coverage::new();
+            stroke_segment(
+                &mut cleared_pixels,
+                pixel_center(start.0 as f64, start.1 as f64),
+                pixel_center(end.0 as f64, end.1 as f64),
+                (thickness + 24) as f64,
             );
             let mut modified = false;
 
-            for (x, y) in cleared_pixels {
-                if x < width && y < height {
+            for (x, y) in cleared_pixels.into_keys() {
+                if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                    let (x, y) = (x as usize, y as usize);
                     let true_x = x * ImageFormat::RgbaSeparate.bytes_per_pixel();
                     let true_y = y * width * ImageFormat::RgbaSeparate.bytes_per_pixel();
 
-                    if self.modified_pixel.contains_key(&(true_x, true_y)) {
+                    if let Some(color) = self.original_color(x, y, width) {
                         modified = true;
 
-                        let color = self
-                            .modified_pixel
-                            .get(&(true_x, true_y))
-                            .unwrap()
-                            .to_owned();
-
                         let mask = 0xff000000;
                         for i in 0..ImageFormat::RgbaSeparate.bytes_per_pixel() {
                             pixels[true_x + true_y + i] = ((color & (mask >> i * 8))
@@ -162,7 +761,7 @@ pub mod canvas {
                                 as u8;
                         }
 
-                        self.modified_pixel.remove(&(true_x, true_y));
+                        self.forget_pixel(x, y, width);
                     }
                 }
             }
@@ -175,254 +774,1293 @@ pub mod canvas {
         }
     }
 
-    pub fn generate_line_coordinates(
-        mut start: (f32, f32),
-        mut end: (f32, f32),
-        thickness: usize,
-    ) -> HashSet<(usize, usize)> {
-        if start == end {
-            return HashSet::new();
+    /// A shape drawn over the image, that can still be selected, moved, resized,
+    /// recoloured or deleted. The geometry is in pixels of the image
+    #[derive(Debug, Clone, PartialEq, Data)]
+    pub struct Annotation {
+        pub shape: Shape,
+        pub start: (i32, i32),
+        pub end: (i32, i32),
+        pub color: u32,
+        pub thickness: usize,
+        pub fill: bool,
+        pub text: String,
+        pub font_size: f64,
+        /// Draw a box behind the text, for legibility
+        pub background: bool,
+        /// Heads of an arrow, they are filled if `fill` is set
+        pub heads: ArrowHeads,
+        /// Number shown by a step marker
+        pub number: u32,
+    }
+
+    impl Annotation {
+        pub fn new(
+            shape: Shape,
+            start: (i32, i32),
+            end: (i32, i32),
+            color: u32,
+            thickness: usize,
+            fill: bool,
+        ) -> Self {
+            return Annotation {
+                shape,
+                start,
+                end,
+                color,
+                thickness: thickness.max(1),
+                fill,
+                text: String::new(),
+                font_size: DEFAULT_FONT_SIZE,
+                background: false,
+                heads: ArrowHeads::End,
+                number: 0,
+            };
+        }
+
+        /// Return the length of the heads of an arrow, it grows with the thickness
+        pub fn head_length(&self) -> f64 {
+            return 8.0 + 3.0 * self.thickness as f64;
         }
 
-        let dx = end.0 - start.0;
-        let dy = end.1 - start.1;
+        /// Return the triangles of the heads of an arrow, as (tip, left corner, right corner)
+        pub fn arrow_head_points(&self) -> Vec<[(f64, f64); 3]> {
+            let start = (self.start.0 as f64, self.start.1 as f64);
+            let end = (self.end.0 as f64, self.end.1 as f64);
+
+            let tips = match self.heads {
+                ArrowHeads::None => vec![],
+                ArrowHeads::End => vec![(end, start)],
+                ArrowHeads::Both => vec![(end, start), (start, end)],
+            };
+
+            return tips
+                .into_iter()
+                .filter_map(|(tip, tail)| {
+                    let (dx, dy) = (tip.0 - tail.0, tip.1 - tail.1);
+                    let length = (dx * dx + dy * dy).sqrt();
+                    if length == 0.0 {
+                        return None;
+                    }
+
+                    // the head is never longer than half of the arrow
+                    let head_length = self.head_length().min(length / 2.0);
+                    let (ux, uy) = (dx / length, dy / length);
+                    let base = (tip.0 - ux * head_length, tip.1 - uy * head_length);
+                    let half_width = head_length / 2.0;
 
-        if start.0 > end.0 {
-            let tmp = start.0;
-            start.0 = end.0;
-            end.0 = tmp;
+                    Some([
+                        tip,
+                        (base.0 - uy * half_width, base.1 + ux * half_width),
+                        (base.0 + uy * half_width, base.1 - ux * half_width),
+                    ])
+                })
+                .collect();
         }
-        if start.1 > end.1 {
-            let tmp = start.1;
-            start.1 = end.1;
-            end.1 = tmp;
+
+        /// Return the ends of the shaft of an arrow, that stops at the base of the filled heads
+        pub fn arrow_shaft(&self) -> ((f64, f64), (f64, f64)) {
+            let mut start = (self.start.0 as f64, self.start.1 as f64);
+            let mut end = (self.end.0 as f64, self.end.1 as f64);
+
+            if self.fill {
+                for [tip, left, right] in self.arrow_head_points() {
+                    let base = ((left.0 + right.0) / 2.0, (left.1 + right.1) / 2.0);
+                    if tip == end {
+                        end = base;
+                    } else {
+                        start = base;
+                    }
+                }
+            }
+
+            return (start, end);
         }
 
-        let m = (start.1 - end.1) / (start.0 - end.0);
-        let q = start.1 - m * start.0;
+        /// Create an empty text annotation with the top left corner in `start`
+        pub fn text(start: (i32, i32), color: u32, font_size: f64, background: bool) -> Self {
+            let mut annotation = Annotation::new(Shape::Text, start, start, color, 1, false);
+            annotation.font_size = font_size;
+            annotation.background = background;
+            annotation.update_text_size();
 
-        let mut raw_filled_pixels = Vec::new();
+            return annotation;
+        }
 
-        for x in (start.0 as usize)..=(end.0 as usize) {
-            let y = (m * (x as f32) + q).ceil() as usize; // This is the equation of straight
+        /// Create a step marker, a filled circle showing `number`, with a size that follows the thickness
+        pub fn step(center: (i32, i32), color: u32, thickness: usize, number: u32) -> Self {
+            let mut annotation =
+                Annotation::new(Shape::Step, center, center, color, thickness, true);
+            annotation.number = number;
 
-            raw_filled_pixels.push((x, y));
+            return annotation;
         }
 
-        let mut filled_pixels = HashSet::new();
+        /// Return the radius of the circle of a step marker
+        pub fn step_radius(&self) -> f64 {
+            return 10.0 + 3.0 * self.thickness as f64;
+        }
+
+        /// Return the space between the text and the border of its box
+        pub fn text_padding(&self) -> f64 {
+            return self.font_size * TEXT_PADDING_RATIO;
+        }
 
-        if m == f32::NEG_INFINITY {
-            // The line is vertical
-            // The thickness of straight is on the horizontal
-            let x = (start.0 + (end.0 - start.0) / 2.0) as usize;
-            let y_1 = start.1;
-            let y_2 = end.1;
+        /// Fit the box of a text annotation to its text, after the text or the font changed
+        pub fn update_text_size(&mut self) {
+            let size = measure_text(self);
+            let padding = self.text_padding();
 
-            let min_x = match x.checked_sub(thickness / 2) {
-                Some(sub) => sub,
-                None => 0,
-            };
+            self.end = (
+                self.start.0 + (size.width + 2.0 * padding).ceil() as i32,
+                self.start.1 + (size.height + 2.0 * padding).ceil() as i32,
+            );
+        }
+
+        /// Return the colour of the box behind a text, in contrast with the colour of the text
+        pub fn background_color(&self) -> u32 {
+            let [r, g, b, _] = self.color.to_be_bytes();
+            let luminance = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
 
-            for x_th in min_x..=(x + thickness / 2) {
-                for y in (y_1 as usize)..(y_2 as usize) {
-                    filled_pixels.insert((x_th, y));
+            if luminance > 140.0 {
+                return 0x000000c0;
+            }
+            return 0xffffffc0;
+        }
+
+        /// Return the colour of the number of a step marker, in contrast with the colour of the circle
+        pub fn number_color(&self) -> u32 {
+            return self.background_color() | 0xff;
+        }
+
+        /// Return the corners of the box of the annotation as (x0, y0, x1, y1).
+        /// The box of a circle is a square with the side of the longest edge of the dragged rect
+        pub fn bounds(&self) -> (i32, i32, i32, i32) {
+            if self.shape == Shape::Step {
+                let radius = self.step_radius().ceil() as i32;
+                return (
+                    self.start.0 - radius,
+                    self.start.1 - radius,
+                    self.start.0 + radius,
+                    self.start.1 + radius,
+                );
+            }
+
+            let (x0, x1) = (self.start.0.min(self.end.0), self.start.0.max(self.end.0));
+            let (y0, y1) = (self.start.1.min(self.end.1), self.start.1.max(self.end.1));
+
+            if self.shape == Shape::Cirle {
+                let side = (x1 - x0).max(y1 - y0);
+                return (x0, y0, x0 + side, y0 + side);
+            }
+
+            return (x0, y0, x1, y1);
+        }
+
+        /// Return the points that can be dragged to resize the annotation
+        pub fn handles(&self) -> Vec<(i32, i32)> {
+            match self.shape {
+                Shape::Line | Shape::Arrow => vec![self.start, self.end],
+                // the size of a text follows its font size, the one of a step its thickness
+                Shape::Text | Shape::Step => vec![],
+                _ => vec![
+                    self.start,
+                    (self.end.0, self.start.1),
+                    self.end,
+                    (self.start.0, self.end.1),
+                ],
+            }
+        }
+
+        /// Move the handle at `index` to `point`
+        pub fn move_handle(&mut self, index: usize, point: (i32, i32)) {
+            match (self.shape, index) {
+                (_, 0) => self.start = point,
+                (Shape::Line | Shape::Arrow, _) => self.end = point,
+                (_, 1) => {
+                    self.end.0 = point.0;
+                    self.start.1 = point.1;
+                }
+                (_, 2) => self.end = point,
+                _ => {
+                    self.start.0 = point.0;
+                    self.end.1 = point.1;
                 }
             }
-        } else if m > 1.0 {
-            // The thickness of straight is on the horizontal
-            for i in 0..(raw_filled_pixels.len() - 1) {
-                let x = raw_filled_pixels[i].0;
-                let y_1 = raw_filled_pixels[i].1;
-                let y_2 = raw_filled_pixels[i + 1].1;
+        }
+
+        pub fn translate(&mut self, dx: i32, dy: i32) {
+            self.start = (self.start.0 + dx, self.start.1 + dy);
+            self.end = (self.end.0 + dx, self.end.1 + dy);
+        }
+
+        /// Return true if `point` is on the annotation, or on its area if it is filled
+        pub fn hit_test(&self, point: (i32, i32), tolerance: f64) -> bool {
+            let (px, py) = (point.0 as f64, point.1 as f64);
+            let margin = tolerance + self.thickness as f64 / 2.0;
+            let (x0, y0, x1, y1) = self.bounds();
+            let (x0, y0, x1, y1) = (x0 as f64, y0 as f64, x1 as f64, y1 as f64);
 
-                let min_x = match x.checked_sub(thickness / 2) {
-                    Some(sub) => sub,
-                    None => 0,
-                };
+            match self.shape {
+                Shape::Line | Shape::Arrow => {
+                    let (ax, ay) = (self.start.0 as f64, self.start.1 as f64);
+                    let (bx, by) = (self.end.0 as f64, self.end.1 as f64);
+                    let length = (bx - ax).powi(2) + (by - ay).powi(2);
+                    let t = if length == 0.0 {
+                        0.0
+                    } else {
+                        (((px - ax) * (bx - ax) + (py - ay) * (by - ay)) / length).clamp(0.0, 1.0)
+                    };
+                    let distance = ((px - ax - t * (bx - ax)).powi(2)
+                        + (py - ay - t * (by - ay)).powi(2))
+                    .sqrt();
+
+                    return distance <= margin;
+                }
+                Shape::Cirle => {
+                    let center = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+                    let radius = (x1 - x0) / 2.0;
+                    let distance = ((px - center.0).powi(2) + (py - center.1).powi(2)).sqrt();
 
-                for x_th in min_x..=(x + thickness / 2) {
-                    for y in y_1..y_2 {
-                        filled_pixels.insert((x_th, y));
+                    if self.fill {
+                        return distance <= radius + margin;
                     }
+                    return (distance - radius).abs() <= margin;
                 }
-            }
-        } else {
-            // The thickness of straight is on the vertical
-            for (x, y) in raw_filled_pixels {
-                let min_y = match y.checked_sub(thickness / 2) {
-                    Some(sub) => sub,
-                    None => 0,
-                };
+                Shape::Step => {
+                    let (cx, cy) = (self.start.0 as f64, self.start.1 as f64);
+                    let distance = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+
+                    return distance <= self.step_radius() + tolerance;
+                }
+                Shape::Ellipse => {
+                    let radii = ((x1 - x0) / 2.0, (y1 - y0) / 2.0);
+                    let center = (x0 + radii.0, y0 + radii.1);
+                    let distance = ellipse_distance((px, py), center, radii);
 
-                for y_th in min_y..=(y + thickness / 2) {
-                    filled_pixels.insert((x, y_th));
+                    if self.fill {
+                        return distance <= margin;
+                    }
+                    return distance.abs() <= margin;
+                }
+                _ => {
+                    let inside = px >= x0 - margin
+                        && px <= x1 + margin
+                        && py >= y0 - margin
+                        && py <= y1 + margin;
+
+                    if self.fill || self.shape == Shape::Text {
+                        return inside;
+                    }
+                    let inner = px > x0 + margin
+                        && px < x1 - margin
+                        && py > y0 + margin
+                        && py < y1 - margin;
+                    return inside && !inner;
                 }
             }
         }
 
-        // This is the case where the mouse drag occurred from top left to bottom right or vice versa
-        // This step is necessary for the straight to have the right orientation
-        if (dx > 0.0 && dy < 0.0) || (dx < 0.0 && dy > 0.0) {
-            let tmp = filled_pixels.clone();
+        /// Return the coverage of the pixels of the image by the annotation,
+        /// they can be outside of the image after a crop. The strokes have round caps and joins
+        pub fn coverage(&self) -> Coverage {
+            let mut coverage = Coverage::new();
+            let thickness = self.thickness as f64;
+            let (x0, y0, x1, y1) = self.bounds();
+            // the box includes the pixels of both corners
+            let (x0, y0, x1, y1) = (x0 as f64, y0 as f64, (x1 + 1) as f64, (y1 + 1) as f64);
+
+            match self.shape {
+                Shape::Line => stroke_segment(
+                    &mut coverage,
+                    pixel_center(self.start.0 as f64, self.start.1 as f64),
+                    pixel_center(self.end.0 as f64, self.end.1 as f64),
+                    thickness,
+                ),
+                Shape::Rectangle => {
+                    if self.fill {
+                        fill_rect(&mut coverage, (x0, y0, x1, y1));
+                    } else {
+                        // the stroke is inside the box
+                        let inset = thickness / 2.0;
+                        let corners = [
+                            (x0 + inset, y0 + inset),
+                            (x1 - inset, y0 + inset),
+                            (x1 - inset, y1 - inset),
+                            (x0 + inset, y1 - inset),
+                        ];
+                        for i in 0..corners.len() {
+                            let next = corners[(i + 1) % corners.len()];
+                            stroke_segment(&mut coverage, corners[i], next, thickness);
+                        }
+                    }
+                }
+                Shape::Cirle => {
+                    let radius = (x1 - x0) / 2.0;
+                    let center = (x0 + radius, y0 + radius);
 
-            filled_pixels.clear();
+                    if self.fill {
+                        fill_circle(&mut coverage, center, radius);
+                    } else {
+                        // the stroke is inside the box
+                        let radius = (radius - thickness / 2.0).max(0.0);
+                        stroke_circle(&mut coverage, center, radius, thickness);
+                    }
+                }
+                Shape::Step => fill_circle(
+                    &mut coverage,
+                    pixel_center(self.start.0 as f64, self.start.1 as f64),
+                    self.step_radius(),
+                ),
+                Shape::Ellipse => {
+                    let radii = ((x1 - x0) / 2.0, (y1 - y0) / 2.0);
+                    let center = (x0 + radii.0, y0 + radii.1);
 
-            for (x, y) in tmp {
-                let x_s = ((x as f32) + (end.0 - x as f32) - ((x as f32) - start.0)) as usize;
-                filled_pixels.insert((x_s, y));
+                    if self.fill {
+                        fill_ellipse(&mut coverage, center, radii);
+                    } else {
+                        stroke_ellipse(&mut coverage, center, radii, thickness);
+                    }
+                }
+                Shape::Arrow => {
+                    let (shaft_start, shaft_end) = self.arrow_shaft();
+                    stroke_segment(
+                        &mut coverage,
+                        pixel_center(shaft_start.0, shaft_start.1),
+                        pixel_center(shaft_end.0, shaft_end.1),
+                        thickness,
+                    );
+
+                    for [tip, left, right] in self.arrow_head_points() {
+                        let tip = pixel_center(tip.0, tip.1);
+                        let left = pixel_center(left.0, left.1);
+                        let right = pixel_center(right.0, right.1);
+
+                        if self.fill {
+                            fill_triangle(&mut coverage, [tip, left, right]);
+                        } else {
+                            stroke_segment(&mut coverage, left, tip, thickness);
+                            stroke_segment(&mut coverage, tip, right, thickness);
+                        }
+                    }
+                }
+                _ => {}
             }
+
+            return coverage;
+        }
+    }
+
+    /// Return the layout of the text of an annotation, in pixels of the image.
+    /// It is used both to show the text and to draw it on the saved image
+    pub fn build_text_layout<R: RenderContext>(
+        rc: &mut R,
+        annotation: &Annotation,
+    ) -> Option<R::TextLayout> {
+        return rc
+            .text()
+            .new_text_layout(annotation.text.clone())
+            .font(FontFamily::SYSTEM_UI, annotation.font_size)
+            .text_color(Color::from_rgba32_u32(annotation.color))
+            .build()
+            .ok();
+    }
+
+    /// Return the size of the text of an annotation, at least one line high
+    fn measure_text(annotation: &Annotation) -> Size {
+        let empty = Size::new(0.0, annotation.font_size);
+
+        let mut device = match Device::new() {
+            Ok(device) => device,
+            Err(_) => return empty,
+        };
+        let mut target = match device.bitmap_target(1, 1, 1.0) {
+            Ok(target) => target,
+            Err(_) => return empty,
+        };
+        let mut rc = target.render_context();
+
+        let size = match build_text_layout(&mut rc, annotation) {
+            Some(layout) => layout.size(),
+            None => empty,
+        };
+        let _ = rc.finish();
+
+        return Size::new(size.width, size.height.max(annotation.font_size));
+    }
+
+    /// Draw a text annotation, with its box if it has a background
+    fn paint_text<R: RenderContext>(rc: &mut R, annotation: &Annotation) {
+        let (x0, y0, x1, y1) = annotation.bounds();
+
+        if annotation.background {
+            rc.fill(
+                Rect::new(x0 as f64, y0 as f64, x1 as f64, y1 as f64),
+                &Color::from_rgba32_u32(annotation.background_color()),
+            );
+        }
+        if let Some(layout) = build_text_layout(rc, annotation) {
+            let padding = annotation.text_padding();
+            rc.draw_text(&layout, (x0 as f64 + padding, y0 as f64 + padding));
         }
+    }
+
+    /// Draw the number of a step marker, centered in its box
+    fn paint_step_number<R: RenderContext>(rc: &mut R, annotation: &Annotation) {
+        let (x0, y0, x1, y1) = annotation.bounds();
 
-        return filled_pixels;
+        if let Ok(layout) = rc
+            .text()
+            .new_text_layout(annotation.number.to_string())
+            .font(FontFamily::SYSTEM_UI, annotation.step_radius())
+            .default_attribute(FontWeight::BOLD)
+            .text_color(Color::from_rgba32_u32(annotation.number_color()))
+            .build()
+        {
+            // the circle is centered on the middle of the pixel of its center
+            let size = layout.size();
+            rc.draw_text(
+                &layout,
+                (
+                    x0 as f64 + ((x1 - x0) as f64 - size.width) / 2.0 + 0.5,
+                    y0 as f64 + ((y1 - y0) as f64 - size.height) / 2.0 + 0.5,
+                ),
+            );
+        }
     }
 
-    pub fn generate_empty_rectangle_coordinates(
-        mut start: (usize, usize),
-        mut end: (usize, usize),
-        thickness: usize,
-    ) -> HashSet<(usize, usize)> {
-        if start.0 > end.0 {
-            let tmp = start.0;
-            start.0 = end.0;
-            end.0 = tmp;
+    /// Draw an annotation with `rc`, in pixels of the image. The shapes follow
+    /// the geometry of `Annotation::coverage`, so that the editor can show them over
+    /// the image as they are saved, without drawing them on its pixels
+    pub fn paint_annotation<R: RenderContext>(rc: &mut R, annotation: &Annotation) {
+        let color = Color::from_rgba32_u32(annotation.color);
+        let thickness = annotation.thickness as f64;
+        let style = StrokeStyle::new()
+            .line_cap(LineCap::Round)
+            .line_join(LineJoin::Round);
+        let (x0, y0, x1, y1) = annotation.bounds();
+        // the box includes the pixels of both corners
+        let (x0, y0, x1, y1) = (x0 as f64, y0 as f64, (x1 + 1) as f64, (y1 + 1) as f64);
+
+        match annotation.shape {
+            Shape::Line => rc.stroke_styled(
+                Line::new(
+                    pixel_center(annotation.start.0 as f64, annotation.start.1 as f64),
+                    pixel_center(annotation.end.0 as f64, annotation.end.1 as f64),
+                ),
+                &color,
+                thickness,
+                &style,
+            ),
+            Shape::Rectangle => {
+                if annotation.fill {
+                    rc.fill(Rect::new(x0, y0, x1, y1), &color);
+                } else {
+                    // the stroke is inside the box
+                    let inset = thickness / 2.0;
+                    rc.stroke_styled(
+                        Rect::new(x0 + inset, y0 + inset, x1 - inset, y1 - inset),
+                        &color,
+                        thickness,
+                        &style,
+                    );
+                }
+            }
+            Shape::Cirle => {
+                let radius = (x1 - x0) / 2.0;
+                let center = (x0 + radius, y0 + radius);
+
+                if annotation.fill {
+                    rc.fill(Circle::new(center, radius), &color);
+                } else {
+                    let radius = (radius - thickness / 2.0).max(0.0);
+                    rc.stroke(Circle::new(center, radius), &color, thickness);
+                }
+            }
+            Shape::Ellipse => {
+                let radii = ((x1 - x0) / 2.0, (y1 - y0) / 2.0);
+                let center = (x0 + radii.0, y0 + radii.1);
+
+                // the stroke is inside the ellipse, it fills it when it is wider
+                if annotation.fill || radii.0 <= thickness || radii.1 <= thickness {
+                    rc.fill(Ellipse::new(center, radii, 0.0), &color);
+                } else {
+                    let radii = (radii.0 - thickness / 2.0, radii.1 - thickness / 2.0);
+                    rc.stroke(Ellipse::new(center, radii, 0.0), &color, thickness);
+                }
+            }
+            Shape::Arrow => {
+                // the shaft and the outlined heads are a single path, so that they are blended once
+                let (shaft_start, shaft_end) = annotation.arrow_shaft();
+                let mut path = BezPath::new();
+                path.move_to(pixel_center(shaft_start.0, shaft_start.1));
+                path.line_to(pixel_center(shaft_end.0, shaft_end.1));
+
+                for [tip, left, right] in annotation.arrow_head_points() {
+                    let tip = pixel_center(tip.0, tip.1);
+                    let left = pixel_center(left.0, left.1);
+                    let right = pixel_center(right.0, right.1);
+
+                    if annotation.fill {
+                        let mut head = BezPath::new();
+                        head.move_to(tip);
+                        head.line_to(left);
+                        head.line_to(right);
+                        head.close_path();
+                        rc.fill(head, &color);
+                    } else {
+                        path.move_to(left);
+                        path.line_to(tip);
+                        path.line_to(right);
+                    }
+                }
+
+                rc.stroke_styled(path, &color, thickness, &style);
+            }
+            Shape::Step => {
+                let center = pixel_center(annotation.start.0 as f64, annotation.start.1 as f64);
+                rc.fill(Circle::new(center, annotation.step_radius()), &color);
+                paint_step_number(rc, annotation);
+            }
+            Shape::Text => paint_text(rc, annotation),
+            _ => {}
         }
-        if start.1 > end.1 {
-            let tmp = start.1;
-            start.1 = end.1;
-            end.1 = tmp;
+    }
+
+    /// Draw a text annotation in a bitmap of the size of its box.
+    /// Return the pixels in premultiplied RGBA format, with the size of the bitmap
+    fn rasterize_text(annotation: &Annotation) -> Option<(Vec<u8>, usize, usize)> {
+        let (x0, y0, x1, y1) = annotation.bounds();
+        let (width, height) = ((x1 - x0).max(1) as usize, (y1 - y0).max(1) as usize);
+
+        let mut device = Device::new().ok()?;
+        let mut target = device.bitmap_target(width, height, 1.0).ok()?;
+        {
+            let mut rc = target.render_context();
+
+            rc.transform(Affine::translate((-x0 as f64, -y0 as f64)));
+            paint_text(&mut rc, annotation);
+
+            rc.finish().ok()?;
+        }
+
+        let image = target.to_image_buf(ImageFormat::RgbaPremul).ok()?;
+
+        return Some((image.raw_pixels().to_vec(), width, height));
+    }
+
+    /// Draw the number of a step marker, centered in a bitmap of the size of its box.
+    /// Return the pixels in premultiplied RGBA format, with the size of the bitmap
+    fn rasterize_step_number(annotation: &Annotation) -> Option<(Vec<u8>, usize, usize)> {
+        let (x0, y0, x1, y1) = annotation.bounds();
+        let (width, height) = ((x1 - x0).max(1) as usize, (y1 - y0).max(1) as usize);
+
+        let mut device = Device::new().ok()?;
+        let mut target = device.bitmap_target(width, height, 1.0).ok()?;
+        {
+            let mut rc = target.render_context();
+
+            rc.transform(Affine::translate((-x0 as f64, -y0 as f64)));
+            paint_step_number(&mut rc, annotation);
+
+            rc.finish().ok()?;
         }
 
-        let mut filled_pixels = HashSet::new();
+        let image = target.to_image_buf(ImageFormat::RgbaPremul).ok()?;
 
-        for y in start.1..=end.1 {
-            for x in start.0..=end.0 {
-                if y < start.1 + thickness {
-                    filled_pixels.insert((x, y));
-                } else if y > end.1 - thickness && y <= end.1 {
-                    filled_pixels.insert((x, y));
-                } else if x < start.0 + thickness {
-                    filled_pixels.insert((x, y));
-                } else if x > end.0 - thickness && x <= end.0 {
-                    filled_pixels.insert((x, y));
+        return Some((image.raw_pixels().to_vec(), width, height));
+    }
+
+    /// Draw the annotations, in order, on the pixels of the image.
+    /// It is used to save and copy the image, the editor paints them with `paint_annotation`
+    pub fn flatten_annotations<'a>(
+        annotations: impl Iterator<Item = &'a Annotation>,
+        mut pixels: Vec<u8>,
+        width: usize,
+        height: usize,
+    ) -> Vec<u8> {
+        for annotation in annotations {
+            if annotation.shape == Shape::Text {
+                if let Some((text_pixels, text_width, text_height)) = rasterize_text(annotation) {
+                    blend_premultiplied(
+                        &mut pixels,
+                        (width, height),
+                        &text_pixels,
+                        (text_width, text_height),
+                        annotation.bounds(),
+                    );
+                }
+                continue;
+            }
+
+            for ((x, y), coverage) in annotation.coverage() {
+                if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                    let index = (y as usize * width + x as usize)
+                        * ImageFormat::RgbaSeparate.bytes_per_pixel();
+                    blend_color(&mut pixels, index, annotation.color, coverage);
+                }
+            }
+
+            if annotation.shape == Shape::Step {
+                if let Some((number_pixels, number_width, number_height)) =
+                    rasterize_step_number(annotation)
+                {
+                    blend_premultiplied(
+                        &mut pixels,
+                        (width, height),
+                        &number_pixels,
+                        (number_width, number_height),
+                        annotation.bounds(),
+                    );
                 }
             }
         }
 
-        return filled_pixels;
+        return pixels;
     }
 
-    fn generate_empty_circle_coordinates(
-        mut start: (usize, usize),
-        mut end: (usize, usize),
-        thickness: usize,
-    ) -> HashSet<(usize, usize)> {
-        if start.0 > end.0 {
-            let tmp = start.0;
-            start.0 = end.0;
-            end.0 = tmp;
+    /// Return the center of the pixel at (x, y)
+    fn pixel_center(x: f64, y: f64) -> (f64, f64) {
+        return (x + 0.5, y + 0.5);
+    }
+
+    /// Store the coverage of a pixel, a pixel covered by more parts of a shape
+    /// keeps the highest coverage, so that the shape is drawn only once
+    fn add_coverage(coverage: &mut Coverage, pixel: (i32, i32), value: f64) {
+        let value = value.clamp(0.0, 1.0);
+        if value <= 0.0 {
+            return;
+        }
+
+        let current = coverage.entry(pixel).or_insert(0.0);
+        if value > *current {
+            *current = value;
         }
-        if start.1 > end.1 {
-            let tmp = start.1;
-            start.1 = end.1;
-            end.1 = tmp;
+    }
+
+    /// Call `f` with every pixel of the box (x0, y0, x1, y1) and the position of its center
+    fn for_each_pixel(area: (f64, f64, f64, f64), mut f: impl FnMut((i32, i32), (f64, f64))) {
+        let (x0, y0) = (area.0.floor() as i32, area.1.floor() as i32);
+        let (x1, y1) = (area.2.ceil() as i32, area.3.ceil() as i32);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                f((x, y), pixel_center(x as f64, y as f64));
+            }
+        }
+    }
+
+    /// Add the coverage of a segment from `a` to `b` with round caps,
+    /// the coverage of a pixel is its distance from the border of the stroke, clamped to one pixel
+    fn stroke_segment(coverage: &mut Coverage, a: (f64, f64), b: (f64, f64), width: f64) {
+        let half_width = width / 2.0;
+        let area = (
+            a.0.min(b.0) - half_width - 1.0,
+            a.1.min(b.1) - half_width - 1.0,
+            a.0.max(b.0) + half_width + 1.0,
+            a.1.max(b.1) + half_width + 1.0,
+        );
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let length = dx * dx + dy * dy;
+
+        for_each_pixel(area, |pixel, (px, py)| {
+            let t = if length == 0.0 {
+                0.0
+            } else {
+                (((px - a.0) * dx + (py - a.1) * dy) / length).clamp(0.0, 1.0)
+            };
+            let distance = ((px - a.0 - t * dx).powi(2) + (py - a.1 - t * dy).powi(2)).sqrt();
+
+            add_coverage(coverage, pixel, half_width - distance + 0.5);
+        });
+    }
+
+    /// Add the coverage of a circle outline, with `radius` in the middle of the stroke
+    fn stroke_circle(coverage: &mut Coverage, center: (f64, f64), radius: f64, width: f64) {
+        let extent = radius + width / 2.0 + 1.0;
+        let area = (
+            center.0 - extent,
+            center.1 - extent,
+            center.0 + extent,
+            center.1 + extent,
+        );
+
+        for_each_pixel(area, |pixel, (px, py)| {
+            let distance = ((px - center.0).powi(2) + (py - center.1).powi(2)).sqrt();
+            add_coverage(
+                coverage,
+                pixel,
+                width / 2.0 - (distance - radius).abs() + 0.5,
+            );
+        });
+    }
+
+    fn fill_circle(coverage: &mut Coverage, center: (f64, f64), radius: f64) {
+        let extent = radius + 1.0;
+        let area = (
+            center.0 - extent,
+            center.1 - extent,
+            center.0 + extent,
+            center.1 + extent,
+        );
+
+        for_each_pixel(area, |pixel, (px, py)| {
+            let distance = ((px - center.0).powi(2) + (py - center.1).powi(2)).sqrt();
+            add_coverage(coverage, pixel, radius - distance + 0.5);
+        });
+    }
+
+    /// Return the distance of `point` from the border of the ellipse, negative inside it.
+    /// It is the first order approximation of the distance, exact enough near the border
+    fn ellipse_distance(point: (f64, f64), center: (f64, f64), radii: (f64, f64)) -> f64 {
+        let (rx, ry) = (radii.0.max(0.5), radii.1.max(0.5));
+        let (x, y) = (point.0 - center.0, point.1 - center.1);
+
+        let value = (x / rx).powi(2) + (y / ry).powi(2) - 1.0;
+        let gradient = ((2.0 * x / (rx * rx)).powi(2) + (2.0 * y / (ry * ry)).powi(2)).sqrt();
+        if gradient == 0.0 {
+            // the center of the ellipse
+            return -rx.min(ry);
         }
 
-        if end.0 - start.0 < end.1 - start.1 {
-            end.0 = end.0 + ((end.1 - start.1) - (end.0 - start.0));
-        } else {
-            end.1 = end.1 + ((end.0 - start.0) - (end.1 - start.1));
+        return value / gradient;
+    }
+
+    fn fill_ellipse(coverage: &mut Coverage, center: (f64, f64), radii: (f64, f64)) {
+        let area = (
+            center.0 - radii.0 - 1.0,
+            center.1 - radii.1 - 1.0,
+            center.0 + radii.0 + 1.0,
+            center.1 + radii.1 + 1.0,
+        );
+
+        for_each_pixel(area, |pixel, p| {
+            add_coverage(coverage, pixel, 0.5 - ellipse_distance(p, center, radii));
+        });
+    }
+
+    /// Add the coverage of an ellipse outline, the stroke is inside the ellipse
+    /// and its width is measured from the border, toward the center
+    fn stroke_ellipse(coverage: &mut Coverage, center: (f64, f64), radii: (f64, f64), width: f64) {
+        let inner_radii = (radii.0 - width, radii.1 - width);
+        if inner_radii.0 <= 0.0 || inner_radii.1 <= 0.0 {
+            // the stroke is wider than the ellipse
+            fill_ellipse(coverage, center, radii);
+            return;
         }
 
-        let center = ((start.0 + end.0) / 2, (start.1 + end.1) / 2);
-        let mut radius = (end.0 - start.0) / 2;
+        let area = (
+            center.0 - radii.0 - 1.0,
+            center.1 - radii.1 - 1.0,
+            center.0 + radii.0 + 1.0,
+            center.1 + radii.1 + 1.0,
+        );
+
+        for_each_pixel(area, |pixel, p| {
+            let outer = 0.5 - ellipse_distance(p, center, radii);
+            let inner = 0.5 + ellipse_distance(p, center, inner_radii);
+            add_coverage(coverage, pixel, outer.min(inner));
+        });
+    }
+
+    /// Add the coverage of the box (x0, y0, x1, y1), the area of every pixel inside it
+    fn fill_rect(coverage: &mut Coverage, area: (f64, f64, f64, f64)) {
+        for_each_pixel(area, |pixel, (px, py)| {
+            let covered_x = (px + 0.5).min(area.2) - (px - 0.5).max(area.0);
+            let covered_y = (py + 0.5).min(area.3) - (py - 0.5).max(area.1);
+            add_coverage(coverage, pixel, covered_x.max(0.0) * covered_y.max(0.0));
+        });
+    }
+
+    /// Add the coverage of a triangle, from the distance of the pixels from its nearest edge
+    fn fill_triangle(coverage: &mut Coverage, points: [(f64, f64); 3]) {
+        let area = (
+            points.iter().map(|p| p.0).fold(f64::MAX, f64::min) - 1.0,
+            points.iter().map(|p| p.1).fold(f64::MAX, f64::min) - 1.0,
+            points.iter().map(|p| p.0).fold(f64::MIN, f64::max) + 1.0,
+            points.iter().map(|p| p.1).fold(f64::MIN, f64::max) + 1.0,
+        );
 
-        if radius < thickness {
-            radius = thickness;
+        // the edges are oriented so that the distance is positive inside the triangle
+        let orientation = {
+            let (a, b, c) = (points[0], points[1], points[2]);
+            ((b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)).signum()
+        };
+        if orientation == 0.0 {
+            return;
         }
 
-        let mut filled_pixels = HashSet::new();
+        for_each_pixel(area, |pixel, p| {
+            let mut nearest = f64::MAX;
 
-        for x in start.0..=end.0 {
-            for y in start.1..=end.1 {
-                let distance = ((center.0 as f32 - x as f32).powf(2.0)
-                    + (center.1 as f32 - y as f32).powf(2.0))
-                .sqrt()
-                .ceil() as usize;
+            for i in 0..points.len() {
+                let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+                if length == 0.0 {
+                    continue;
+                }
+
+                let distance =
+                    orientation * ((b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)) / length;
+                nearest = nearest.min(distance);
+            }
+
+            add_coverage(coverage, pixel, nearest + 0.5);
+        });
+    }
+
+    /// Return, for every pixel of the image, whether it is in the area of similar colour
+    /// connected to `seed`. The area is filled a row span at a time, using a stack
+    /// instead of recursion so that large areas can't overflow it
+    fn flood_fill_area(
+        pixels: &[u8],
+        size: (usize, usize),
+        seed: (usize, usize),
+        tolerance: f64,
+        connectivity: Connectivity,
+    ) -> Vec<bool> {
+        let (width, height) = size;
+        let bytes_per_pixel = ImageFormat::RgbaSeparate.bytes_per_pixel();
+        let seed_index = (seed.1 * width + seed.0) * bytes_per_pixel;
+        let seed_color = &pixels[seed_index..seed_index + bytes_per_pixel];
+
+        let mut filled = vec![false; width * height];
+        let matches = |filled: &[bool], x: usize, y: usize| {
+            let i = y * width + x;
+            if filled[i] {
+                return false;
+            }
 
-                if distance <= radius && distance >= (radius - thickness) {
-                    filled_pixels.insert((x, y));
+            let pixel = &pixels[i * bytes_per_pixel..(i + 1) * bytes_per_pixel];
+            return pixel
+                .iter()
+                .zip(seed_color)
+                .all(|(a, b)| (*a as f64 - *b as f64).abs() <= tolerance);
+        };
+
+        let mut stack = vec![seed];
+        while let Some((x, y)) = stack.pop() {
+            if !matches(&filled, x, y) {
+                continue;
+            }
+
+            let mut left = x;
+            while left > 0 && matches(&filled, left - 1, y) {
+                left -= 1;
+            }
+            let mut right = x;
+            while right + 1 < width && matches(&filled, right + 1, y) {
+                right += 1;
+            }
+            for i in left..=right {
+                filled[y * width + i] = true;
+            }
+
+            // with 8 neighbours the spans touching the corners of this one are reached too
+            let (from, to) = match connectivity {
+                Connectivity::Four => (left, right),
+                Connectivity::Eight => (left.saturating_sub(1), (right + 1).min(width - 1)),
+            };
+            let rows = [y.checked_sub(1), Some(y + 1).filter(|row| *row < height)];
+
+            for row in rows.into_iter().flatten() {
+                // a single pixel of every span of the row is enough to fill it
+                let mut in_span = false;
+                for i in from..=to {
+                    if matches(&filled, i, row) {
+                        if !in_span {
+                            stack.push((i, row));
+                            in_span = true;
+                        }
+                    } else {
+                        in_span = false;
+                    }
                 }
             }
         }
 
-        return filled_pixels;
+        return filled;
     }
 
-    fn generate_fill_rectangle_coordinates(
-        mut start: (usize, usize),
-        mut end: (usize, usize),
-    ) -> HashSet<(usize, usize)> {
-        if start.0 > end.0 {
-            let tmp = start.0;
-            start.0 = end.0;
-            end.0 = tmp;
+    /// Replace every block of `block` x `block` pixels of the area (x0, y0, x1, y1)
+    /// with its average colour
+    fn pixelate_area(
+        pixels: &mut [u8],
+        width: usize,
+        area: (usize, usize, usize, usize),
+        block: usize,
+    ) {
+        let (x0, y0, x1, y1) = area;
+        let block = block.max(2);
+
+        for block_y in (y0..y1).step_by(block) {
+            for block_x in (x0..x1).step_by(block) {
+                let (end_x, end_y) = ((block_x + block).min(x1), (block_y + block).min(y1));
+                let mut sum = [0usize; 3];
+
+                for y in block_y..end_y {
+                    for x in block_x..end_x {
+                        let index = (y * width + x) * 4;
+                        for c in 0..3 {
+                            sum[c] += pixels[index + c] as usize;
+                        }
+                    }
+                }
+
+                let count = (end_x - block_x) * (end_y - block_y);
+                let color = u32::from_be_bytes([
+                    (sum[0] / count) as u8,
+                    (sum[1] / count) as u8,
+                    (sum[2] / count) as u8,
+                    255,
+                ]);
+                fill_area(pixels, width, (block_x, block_y, end_x, end_y), color);
+            }
         }
-        if start.1 > end.1 {
-            let tmp = start.1;
-            start.1 = end.1;
-            end.1 = tmp;
+    }
+
+    /// Apply a gaussian blur with standard deviation `sigma` to the area (x0, y0, x1, y1).
+    /// The pixels around the area are read but not modified
+    fn blur_area(
+        pixels: &mut [u8],
+        width: usize,
+        height: usize,
+        area: (usize, usize, usize, usize),
+        sigma: f64,
+    ) {
+        let (x0, y0, x1, y1) = area;
+        let radius = (3.0 * sigma).ceil() as i64;
+        let kernel: Vec<f64> = (-radius..=radius)
+            .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let kernel_sum: f64 = kernel.iter().sum();
+
+        // the blur is separable: first the rows, extended vertically to feed the second pass,
+        // then the columns of the area
+        let (ext_y0, ext_y1) = (
+            (y0 as i64 - radius).max(0) as usize,
+            (y1 as i64 + radius).min(height as i64) as usize,
+        );
+        let area_width = x1 - x0;
+        let mut rows = vec![[0.0f64; 3]; area_width * (ext_y1 - ext_y0)];
+
+        for y in ext_y0..ext_y1 {
+            for x in x0..x1 {
+                let mut color = [0.0; 3];
+                for (k, weight) in kernel.iter().enumerate() {
+                    let sample_x =
+                        (x as i64 + k as i64 - radius).clamp(0, width as i64 - 1) as usize;
+                    let index = (y * width + sample_x) * 4;
+                    for c in 0..3 {
+                        color[c] += pixels[index + c] as f64 * weight;
+                    }
+                }
+                rows[(y - ext_y0) * area_width + (x - x0)] = color;
+            }
+        }
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let mut color = [0.0; 3];
+                for (k, weight) in kernel.iter().enumerate() {
+                    let sample_y = (y as i64 + k as i64 - radius)
+                        .clamp(ext_y0 as i64, ext_y1 as i64 - 1)
+                        as usize;
+                    let row = rows[(sample_y - ext_y0) * area_width + (x - x0)];
+                    for c in 0..3 {
+                        color[c] += row[c] * weight;
+                    }
+                }
+
+                let index = (y * width + x) * 4;
+                for c in 0..3 {
+                    pixels[index + c] = (color[c] / (kernel_sum * kernel_sum)).round() as u8;
+                }
+                pixels[index + 3] = 255;
+            }
         }
+    }
 
-        let mut filled_pixels = HashSet::new();
+    fn fill_area(pixels: &mut [u8], width: usize, area: (usize, usize, usize, usize), color: u32) {
+        let (x0, y0, x1, y1) = area;
 
-        for y in start.1..=end.1 {
-            for x in start.0..=end.0 {
-                filled_pixels.insert((x, y));
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let index = (y * width + x) * 4;
+                pixels[index..index + 4].copy_from_slice(&color.to_be_bytes());
             }
         }
+    }
 
-        return filled_pixels;
+    /// Draw a colour in RGBA format over the pixel at `index`, covered for the given fraction,
+    /// with source-over compositing
+    fn blend_color(pixels: &mut [u8], index: usize, color: u32, coverage: f64) {
+        let [r, g, b, a] = color.to_be_bytes();
+        let src_alpha = a as f64 / 255.0 * coverage.clamp(0.0, 1.0);
+        let premultiplied = [
+            r as f64 * src_alpha,
+            g as f64 * src_alpha,
+            b as f64 * src_alpha,
+        ];
+
+        blend_pixel(pixels, index, premultiplied, src_alpha);
     }
 
-    fn generate_fill_circle_coordinates(
-        mut start: (usize, usize),
-        mut end: (usize, usize),
-    ) -> HashSet<(usize, usize)> {
-        if start.0 > end.0 {
-            let tmp = start.0;
-            start.0 = end.0;
-            end.0 = tmp;
+    /// Draw a premultiplied colour over the pixel at `index`, that is not premultiplied
+    fn blend_pixel(pixels: &mut [u8], index: usize, src: [f64; 3], src_alpha: f64) {
+        if src_alpha <= 0.0 {
+            return;
         }
-        if start.1 > end.1 {
-            let tmp = start.1;
-            start.1 = end.1;
-            end.1 = tmp;
+
+        let dst_alpha = pixels[index + 3] as f64 / 255.0;
+        let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+
+        for c in 0..3 {
+            let dst = pixels[index + c] as f64 * dst_alpha;
+            let out = src[c] + dst * (1.0 - src_alpha);
+            pixels[index + c] = (out / out_alpha).round().min(255.0) as u8;
         }
+        pixels[index + 3] = (out_alpha * 255.0).round() as u8;
+    }
 
-        if end.0 - start.0 < end.1 - start.1 {
-            end.0 = end.0 + ((end.1 - start.1) - (end.0 - start.0));
-        } else {
-            end.1 = end.1 + ((end.0 - start.0) - (end.1 - start.1));
+    /// Draw premultiplied RGBA pixels over the image, with the top left corner of `bounds`
+    fn blend_premultiplied(
+        pixels: &mut Vec<u8>,
+        size: (usize, usize),
+        src: &[u8],
+        src_size: (usize, usize),
+        bounds: (i32, i32, i32, i32),
+    ) {
+        for src_y in 0..src_size.1 {
+            for src_x in 0..src_size.0 {
+                let (x, y) = (bounds.0 + src_x as i32, bounds.1 + src_y as i32);
+                if x < 0 || y < 0 || x as usize >= size.0 || y as usize >= size.1 {
+                    continue;
+                }
+
+                let src_index = (src_y * src_size.0 + src_x) * 4;
+                let index = (y as usize * size.0 + x as usize) * 4;
+
+                blend_pixel(
+                    pixels,
+                    index,
+                    [
+                        src[src_index] as f64,
+                        src[src_index + 1] as f64,
+                        src[src_index + 2] as f64,
+                    ],
+                    src[src_index + 3] as f64 / 255.0,
+                );
+            }
         }
+    }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
 
-        let center = ((start.0 + end.0) / 2, (start.1 + end.1) / 2);
-        let radius = (end.0 - start.0) / 2;
+        const WHITE: [u8; 4] = [255, 255, 255, 255];
+        const BLACK: [u8; 4] = [0, 0, 0, 255];
 
-        let mut filled_pixels = HashSet::new();
+        /// Return the pixels of an image drawn with `W` for white and `B` for black
+        fn image(rows: &[&str]) -> Vec<u8> {
+            return rows
+                .iter()
+                .flat_map(|row| row.chars())
+                .flat_map(|c| if c == 'W' { WHITE } else { BLACK })
+                .collect();
+        }
 
-        for x in start.0..=end.0 {
-            for y in start.1..=end.1 {
-                let distance = ((center.0 as f32 - x as f32).powf(2.0)
-                    + (center.1 as f32 - y as f32).powf(2.0))
-                .sqrt()
-                .ceil() as usize;
+        /// Return the mask of a fill drawn with `#` for the filled pixels
+        fn mask(rows: &[&str]) -> Vec<bool> {
+            return rows
+                .iter()
+                .flat_map(|row| row.chars())
+                .map(|c| c == '#')
+                .collect();
+        }
 
-                if distance <= radius {
-                    filled_pixels.insert((x, y));
-                }
+        const CHECKERBOARD: [&str; 3] = ["WBW", "BWB", "WBW"];
+
+        #[test]
+        fn flood_fill_with_four_neighbours_stops_at_corners() {
+            let pixels = image(&CHECKERBOARD);
+
+            let filled = flood_fill_area(&pixels, (3, 3), (1, 1), 0.0, Connectivity::Four);
+            assert_eq!(filled, mask(&["...", ".#.", "..."]));
+
+            let pixels = image(&["WWB", "BWB", "WWW"]);
+            let filled = flood_fill_area(&pixels, (3, 3), (0, 0), 0.0, Connectivity::Four);
+            assert_eq!(filled, mask(&["##.", ".#.", "###"]));
+        }
+
+        #[test]
+        fn flood_fill_with_eight_neighbours_crosses_corners() {
+            let pixels = image(&CHECKERBOARD);
+
+            let filled = flood_fill_area(&pixels, (3, 3), (1, 1), 0.0, Connectivity::Eight);
+            assert_eq!(filled, mask(&["#.#", ".#.", "#.#"]));
+
+            let filled = flood_fill_area(&pixels, (3, 3), (1, 0), 0.0, Connectivity::Eight);
+            assert_eq!(filled, mask(&[".#.", "#.#", ".#."]));
+        }
+
+        #[test]
+        fn flood_fill_follows_the_tolerance() {
+            let mut pixels = image(&["WWW"]);
+            pixels[4..7].copy_from_slice(&[245, 245, 245]);
+            pixels[8..11].copy_from_slice(&[244, 255, 255]);
+
+            let filled = flood_fill_area(&pixels, (3, 1), (0, 0), 10.0, Connectivity::Four);
+            assert_eq!(filled, mask(&["##."]));
+        }
+
+        #[test]
+        fn rubber_restores_the_filled_pixels() {
+            let original = image(&CHECKERBOARD);
+            let buf = ImageBuf::from_raw(original.clone(), ImageFormat::RgbaSeparate, 3, 3);
+            let mut canvas = Canvas::new();
+
+            let filled = canvas.flood_fill(&buf, (1, 1)).unwrap();
+            assert_eq!(
+                &filled.raw_pixels()[16..20],
+                &canvas.get_color().to_be_bytes()
+            );
+            assert_eq!(
+                canvas.original_color(1, 1, 3),
+                Some(u32::from_be_bytes(WHITE))
+            );
+            assert_eq!(canvas.original_color(0, 1, 3), None);
+
+            let restored = canvas
+                .clear_pixel(filled.raw_pixels().to_vec(), 3, 3, (1, 1), (1, 1), 1)
+                .unwrap();
+            assert_eq!(restored, original);
+            assert_eq!(canvas.original_color(1, 1, 3), None);
+        }
+
+        #[test]
+        fn fill_follows_the_crop() {
+            let buf = ImageBuf::from_raw(image(&CHECKERBOARD), ImageFormat::RgbaSeparate, 3, 3);
+            let mut canvas = Canvas::new();
+            canvas.flood_fill(&buf, (1, 1)).unwrap();
+
+            // the image is cropped to the 2x2 pixels at the bottom right
+            canvas.crop_fills((1, 1));
+
+            assert_eq!(
+                canvas.original_color(0, 0, 2),
+                Some(u32::from_be_bytes(WHITE))
+            );
+            assert_eq!(canvas.original_color(1, 1, 2), None);
+        }
+
+        #[test]
+        fn painted_rectangle_matches_the_saved_one() {
+            let rectangle = Annotation::new(Shape::Rectangle, (1, 1), (4, 5), 0xff0000ff, 1, true);
+            let saved = flatten_annotations([&rectangle].into_iter(), WHITE.repeat(64), 8, 8);
+
+            let mut device = Device::new().unwrap();
+            let mut target = device.bitmap_target(8, 8, 1.0).unwrap();
+            {
+                let mut rc = target.render_context();
+                rc.fill(Rect::new(0.0, 0.0, 8.0, 8.0), &Color::WHITE);
+                paint_annotation(&mut rc, &rectangle);
+                rc.finish().unwrap();
             }
+            let painted = target.to_image_buf(ImageFormat::RgbaSeparate).unwrap();
+
+            assert_eq!(painted.raw_pixels(), saved.as_slice());
+        }
+
+        /// Return the numbers of the step markers, in the order they are drawn
+        fn step_numbers(canvas: &Canvas) -> Vec<u32> {
+            return canvas
+                .annotations
+                .iter()
+                .filter(|a| a.shape == Shape::Step)
+                .map(|a| a.number)
+                .collect();
+        }
+
+        /// Return a canvas with the steps 1, 2 and 3 and a rectangle between the first two
+        fn canvas_with_steps() -> Canvas {
+            let mut canvas = Canvas::new();
+
+            let step = canvas.new_step((10, 10), 1);
+            canvas.annotations.push_back(step);
+            canvas.annotations.push_back(Annotation::new(
+                Shape::Rectangle,
+                (0, 0),
+                (5, 5),
+                0xff0000ff,
+                1,
+                false,
+            ));
+            for x in [20, 30] {
+                let step = canvas.new_step((x, 10), 1);
+                canvas.annotations.push_back(step);
+            }
+
+            return canvas;
         }
 
-        return filled_pixels;
+        #[test]
+        fn deleting_a_step_renumbers_the_following_ones() {
+            let mut canvas = canvas_with_steps();
+            assert_eq!(step_numbers(&canvas), vec![1, 2, 3]);
+            assert_eq!(canvas.get_next_step(), 4);
+
+            // the step 2, after the rectangle
+            canvas.select(Some(2));
+            canvas.delete_selected();
+
+            assert_eq!(step_numbers(&canvas), vec![1, 2]);
+            assert_eq!(canvas.annotations[2].start, (30, 10));
+            assert_eq!(canvas.get_next_step(), 3);
+            assert_eq!(canvas.get_selected(), None);
+        }
+
+        #[test]
+        fn deleting_the_last_step_keeps_the_others() {
+            let mut canvas = canvas_with_steps();
+
+            canvas.select(Some(3));
+            canvas.delete_selected();
+
+            assert_eq!(step_numbers(&canvas), vec![1, 2]);
+            assert_eq!(canvas.get_next_step(), 3);
+        }
+
+        #[test]
+        fn reset_keeps_the_tool_settings() {
+            let buf = ImageBuf::from_raw(image(&CHECKERBOARD), ImageFormat::RgbaSeparate, 3, 3);
+            let mut canvas = canvas_with_steps();
+            canvas.set_color(0x00ff00ff);
+            canvas.set_font_size(40.0);
+            canvas.set_redaction_mode(RedactionMode::Blur);
+            canvas.set_fill_tolerance(30.0);
+            canvas.flood_fill(&buf, (1, 1)).unwrap();
+
+            canvas.reset_edits();
+
+            assert!(canvas.annotations.is_empty());
+            assert!(!canvas.has_modified_pixels());
+            assert_eq!(canvas.get_color(), 0x00ff00ff);
+            assert_eq!(canvas.get_font_size(), 40.0);
+            assert_eq!(canvas.get_redaction_mode(), RedactionMode::Blur);
+            assert_eq!(canvas.get_fill_tolerance(), 30.0);
+            assert_eq!(canvas.get_next_step(), 4);
+        }
+
+        #[test]
+        fn deleting_another_annotation_keeps_the_steps() {
+            let mut canvas = canvas_with_steps();
+
+            canvas.select(Some(1));
+            canvas.delete_selected();
+
+            assert_eq!(step_numbers(&canvas), vec![1, 2, 3]);
+            assert_eq!(canvas.get_next_step(), 4);
+        }
     }
 }
@@ -0,0 +1,188 @@
+pub mod color {
+    use druid::{Data, Lens};
+
+    /// State of the colour picker of the drawing tools.
+    /// The colour is kept as hue, saturation and value, so that the hue
+    /// is not lost when the colour becomes grey
+    #[derive(Clone, Debug, PartialEq, Data, Lens)]
+    pub struct ColorPicker {
+        /// Hue in degrees, from 0 to 360
+        pub hue: f64,
+        /// Saturation, from 0 to 1
+        pub saturation: f64,
+        /// Value, from 0 to 1
+        pub value: f64,
+        /// Opacity, from 0 to 1
+        pub opacity: f64,
+        /// Colour typed by the user, as hex or RGBA
+        pub text: String,
+        /// The picker is shown in the drawing bar
+        pub open: bool,
+    }
+
+    impl ColorPicker {
+        pub fn new(color: u32) -> Self {
+            let mut picker = ColorPicker {
+                hue: 0.0,
+                saturation: 0.0,
+                value: 0.0,
+                opacity: 1.0,
+                text: String::new(),
+                open: false,
+            };
+            picker.set_color(color);
+
+            return picker;
+        }
+
+        /// Return the colour of the picker in RGBA format
+        pub fn get_color(&self) -> u32 {
+            let (r, g, b) = hsv_to_rgb(self.hue, self.saturation, self.value);
+            let a = (self.opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+            return u32::from_be_bytes([r, g, b, a]);
+        }
+
+        /// Show a colour in RGBA format in the picker
+        pub fn set_color(&mut self, color: u32) {
+            let [r, g, b, a] = color.to_be_bytes();
+            let (hue, saturation, value) = rgb_to_hsv(r, g, b);
+
+            // the hue of a grey is undefined, the previous one is kept
+            if saturation > 0.0 && value > 0.0 {
+                self.hue = hue;
+            }
+            if value > 0.0 {
+                self.saturation = saturation;
+            }
+            self.value = value;
+            self.opacity = a as f64 / 255.0;
+            self.text = format_color(color);
+        }
+    }
+
+    /// Convert a colour from HSV, with the hue in degrees and the others from 0 to 1, to RGB
+    pub fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> (u8, u8, u8) {
+        let hue = hue.rem_euclid(360.0) / 60.0;
+        let chroma = value.clamp(0.0, 1.0) * saturation.clamp(0.0, 1.0);
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let m = value.clamp(0.0, 1.0) - chroma;
+
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let to_byte = |c: f64| ((c + m) * 255.0).round() as u8;
+
+        return (to_byte(r), to_byte(g), to_byte(b));
+    }
+
+    /// Convert a colour from RGB to HSV, with the hue in degrees and the others from 0 to 1
+    pub fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (f64, f64, f64) {
+        let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+        return (hue, saturation, max);
+    }
+
+    /// Return the colour in RGBA format as `#RRGGBBAA`
+    pub fn format_color(color: u32) -> String {
+        return format!("#{:08X}", color);
+    }
+
+    /// Parse a colour typed by the user, in RGBA format.
+    ///
+    /// Accepted formats are:
+    /// - `#RRGGBB` and `#RRGGBBAA`, the `#` is optional
+    /// - `R, G, B` and `R, G, B, A` with values from 0 to 255,
+    ///   optionally inside `rgb(...)` or `rgba(...)`
+    pub fn parse_color(text: &str) -> Option<u32> {
+        let text = text.trim();
+
+        if text.contains(',') {
+            let inner = text
+                .trim_start_matches("rgba")
+                .trim_start_matches("rgb")
+                .trim_start_matches('(')
+                .trim_end_matches(')');
+            let values = inner
+                .split(',')
+                .map(|value| value.trim().parse::<u8>().ok())
+                .collect::<Option<Vec<u8>>>()?;
+
+            return match values[..] {
+                [r, g, b] => Some(u32::from_be_bytes([r, g, b, 0xff])),
+                [r, g, b, a] => Some(u32::from_be_bytes([r, g, b, a])),
+                _ => None,
+            };
+        }
+
+        let hex = text.trim_start_matches('#');
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        return match hex.len() {
+            6 => Some((u32::from_str_radix(hex, 16).ok()? << 8) | 0xff),
+            8 => u32::from_str_radix(hex, 16).ok(),
+            _ => None,
+        };
+    }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn hex_colors_are_parsed() {
+            assert_eq!(parse_color("#FF8000"), Some(0xFF8000FF));
+            assert_eq!(parse_color("ff800080"), Some(0xFF800080));
+            assert_eq!(parse_color("  #00ff00  "), Some(0x00FF00FF));
+        }
+
+        #[test]
+        fn rgba_values_are_parsed() {
+            assert_eq!(parse_color("255, 128, 0"), Some(0xFF8000FF));
+            assert_eq!(parse_color("255,128,0,128"), Some(0xFF800080));
+            assert_eq!(parse_color("rgb(0, 255, 0)"), Some(0x00FF00FF));
+            assert_eq!(parse_color("rgba(0, 0, 255, 0)"), Some(0x0000FF00));
+        }
+
+        #[test]
+        fn invalid_colors_are_rejected() {
+            assert_eq!(parse_color(""), None);
+            assert_eq!(parse_color("#FF80"), None);
+            assert_eq!(parse_color("#GG8000"), None);
+            assert_eq!(parse_color("+F8000"), None);
+            assert_eq!(parse_color("256, 0, 0"), None);
+            assert_eq!(parse_color("0, 0"), None);
+            assert_eq!(parse_color("0, 0, 0, 0, 0"), None);
+        }
+
+        #[test]
+        fn formatted_color_is_parsed_back() {
+            assert_eq!(format_color(0xFF8000FF), "#FF8000FF");
+            assert_eq!(format_color(0x0000000A), "#0000000A");
+
+            for color in [0x00000000, 0x12345678, 0xFFFFFFFF] {
+                assert_eq!(parse_color(&format_color(color)), Some(color));
+            }
+        }
+    }
+}
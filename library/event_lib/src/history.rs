@@ -0,0 +1,148 @@
+pub mod history {
+    use druid::Data;
+
+    /// Maximum number of edits that can be undone
+    const HISTORY_LIMIT: usize = 30;
+
+    /// Undo/redo stack of the states of the editor.
+    /// Before every edit the current state is recorded, undoing an edit
+    /// restores the last recorded state and keeps the current one for redo
+    #[derive(Clone, Debug)]
+    pub struct History<T: Clone> {
+        undo_stack: Vec<T>,
+        redo_stack: Vec<T>,
+        /// Incremented on every change, so that the widgets can follow the history
+        generation: u64,
+    }
+
+    impl<T: Clone> History<T> {
+        pub fn new() -> Self {
+            return History {
+                undo_stack: vec![],
+                redo_stack: vec![],
+                generation: 0,
+            };
+        }
+
+        /// Record the state before an edit, the edits undone so far can't be redone anymore
+        pub fn record(&mut self, state: T) {
+            self.undo_stack.push(state);
+            if self.undo_stack.len() > HISTORY_LIMIT {
+                self.undo_stack.remove(0);
+            }
+            self.redo_stack.clear();
+            self.generation += 1;
+        }
+
+        /// Return the state to restore to undo the last edit,
+        /// `current` is kept to redo it
+        pub fn undo(&mut self, current: T) -> Option<T> {
+            let state = self.undo_stack.pop()?;
+            self.redo_stack.push(current);
+            self.generation += 1;
+
+            return Some(state);
+        }
+
+        /// Return the state to restore to redo the last undone edit,
+        /// `current` is kept to undo it again
+        pub fn redo(&mut self, current: T) -> Option<T> {
+            let state = self.redo_stack.pop()?;
+            self.undo_stack.push(current);
+            self.generation += 1;
+
+            return Some(state);
+        }
+
+        pub fn can_undo(&self) -> bool {
+            return !self.undo_stack.is_empty();
+        }
+
+        pub fn can_redo(&self) -> bool {
+            return !self.redo_stack.is_empty();
+        }
+
+        pub fn clear(&mut self) {
+            self.undo_stack.clear();
+            self.redo_stack.clear();
+            self.generation += 1;
+        }
+    }
+
+    impl<T: Clone + 'static> Data for History<T> {
+        fn same(&self, other: &Self) -> bool {
+            return self.generation == other.generation;
+        }
+    }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn undo_and_redo_walk_the_recorded_states() {
+            let mut history = History::new();
+            assert!(!history.can_undo() && !history.can_redo());
+
+            // the edits change the state from 0 to 1 and from 1 to 2
+            history.record(0);
+            history.record(1);
+
+            assert_eq!(history.undo(2), Some(1));
+            assert_eq!(history.undo(1), Some(0));
+            assert_eq!(history.undo(0), None);
+            assert!(history.can_redo());
+
+            assert_eq!(history.redo(0), Some(1));
+            assert_eq!(history.redo(1), Some(2));
+            assert_eq!(history.redo(2), None);
+            assert!(history.can_undo());
+        }
+
+        #[test]
+        fn new_edit_clears_the_redo_stack() {
+            let mut history = History::new();
+            history.record(0);
+            history.record(1);
+            assert_eq!(history.undo(2), Some(1));
+
+            history.record(1);
+
+            assert!(!history.can_redo());
+            assert_eq!(history.redo(5), None);
+            assert_eq!(history.undo(5), Some(1));
+            assert_eq!(history.undo(1), Some(0));
+        }
+
+        #[test]
+        fn oldest_edits_are_dropped_over_the_limit() {
+            let mut history = History::new();
+            for state in 0..HISTORY_LIMIT + 5 {
+                history.record(state);
+            }
+
+            let mut current = HISTORY_LIMIT + 5;
+            let mut undone = 0;
+            while let Some(state) = history.undo(current) {
+                current = state;
+                undone += 1;
+            }
+
+            assert_eq!(undone, HISTORY_LIMIT);
+            assert_eq!(current, 5);
+        }
+
+        #[test]
+        fn every_change_is_a_new_generation() {
+            let mut history = History::new();
+            let initial = history.clone();
+
+            history.record(0);
+            assert!(!history.same(&initial));
+
+            let recorded = history.clone();
+            history.undo(1);
+            assert!(!history.same(&recorded));
+            assert!(history.same(&history.clone()));
+        }
+    }
+}
@@ -1,21 +1,41 @@
 pub mod canvas;
+pub mod color;
+pub mod history;
 use arboard::{Clipboard, ImageData};
-use canvas::canvas::Canvas;
+use canvas::canvas::{flatten_annotations, Annotation, Canvas, FillLayer, Shape};
 use chrono::Local;
+use color::color::{format_color, parse_color, ColorPicker};
+use history::history::History;
 use druid::{
     im::{HashMap, Vector},
     image::{ImageBuffer, Rgba},
     keyboard_types::Key,
     piet::ImageFormat,
-    AppDelegate, Command, Data, DelegateCtx, Env, Event, ExtEventSink, ImageBuf, Lens, Selector,
-    Target,
+    AppDelegate, Command, Data, DelegateCtx, Env, Event, ExtEventSink, Handled, ImageBuf, Lens,
+    Selector, Target,
 };
 use native_dialog::{FileDialog, MessageDialog};
-use screenshot_lib::*;
+use screenshot_lib::{interval::interval::*, recording::recording::*, source::source::*, *};
 use shortcut_lib::*;
-use std::{borrow::Cow, path::PathBuf, str::FromStr, thread};
+use std::{
+    borrow::Cow,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
 use EditState::*;
 
+/// Time in milliseconds given to the window to be minimized before the capture
+const MINIMIZE_DELAY_MS: u64 = 500;
+
+/// Time in milliseconds between two checks of the connected screens
+const SCREEN_POLL_MS: u64 = 2000;
+
 #[derive(Clone, Data, PartialEq, Eq)]
 pub enum EditState {
     ShortcutEditing(Action),
@@ -23,6 +43,7 @@ pub enum EditState {
     MouseDetecting,
     ImageResize,
     Drawing,
+    WindowPicking,
     None,
 }
 
@@ -32,11 +53,45 @@ pub enum ViewState {
     MenuView,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Data)]
+/// Position of the current image inside the capture of a screen,
+/// used to remember the regions cropped from the screens
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ImageOrigin {
+    screen_index: usize,
+    left_corner: (u32, u32),
+}
+
+/// State of the editor recorded in the history before every edit.
+/// The origin is kept too, so that undoing a crop restores the whole image
+#[derive(Clone, Debug)]
+struct EditSnapshot {
+    buf_view: ImageBuf,
+    modified_pixel: HashMap<(usize, usize), u32>,
+    fills: Vector<FillLayer>,
+    annotations: Vector<Annotation>,
+    /// Number of the next step marker, it follows the markers added or deleted
+    next_step: u32,
+    image_origin: Option<ImageOrigin>,
+    reset_offset: (usize, usize),
+    modified: bool,
+}
+
+/// Resolution used to save an image captured on a scaled display
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Data)]
+pub enum ExportResolution {
+    /// Save every captured pixel
+    Physical,
+    /// Downscale the image to the logical size of the screen
+    Logical,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Data)]
 struct Options {
     save_path: SavePath,
     extension: String,
     shortcuts: Shortcuts,
+    capture_options: CaptureOptions,
+    color_options: ColorOptions,
 }
 
 impl Options {
@@ -45,6 +100,8 @@ impl Options {
             save_path: SavePath::new(),
             extension: String::from_str("jpg").unwrap(),
             shortcuts: Shortcuts::new(),
+            capture_options: CaptureOptions::new(),
+            color_options: ColorOptions::new(),
         }
     }
 
@@ -78,16 +135,57 @@ pub struct AppState {
     buf_view: ImageBuf,
     #[data(ignore)]
     buf_reset: ImageBuf,
+    /// Position of the current image inside `buf_reset`, it moves when the image is cropped
+    #[data(ignore)]
+    reset_offset: (usize, usize),
     text_buffer: String,
     view_state: ViewState,
     edit_state: EditState,
-    screenshot_token: u64,
+    capturing: bool,
+    #[data(ignore)]
+    capture_cancel: Arc<AtomicBool>,
+    interval_seconds: f64,
+    interval_count: f64,
+    interval_keep_last: f64,
+    interval_running: bool,
+    interval_frames: u32,
+    recording_fps: f64,
+    recording_max_seconds: f64,
+    recording_format: RecordingFormat,
+    recording_region: bool,
+    recording_running: bool,
+    recording_frames: u32,
+    scale_factor: f64,
+    export_resolution: ExportResolution,
+    #[data(ignore)]
+    image_origin: Option<ImageOrigin>,
+    /// Origin of `buf_reset`, it is restored with the image by `get_buf_reset`
+    #[data(ignore)]
+    reset_origin: Option<ImageOrigin>,
+    #[data(ignore)]
+    pending_origin: Option<ImageOrigin>,
+    #[data(ignore)]
+    pending_crop: Option<Area>,
+    #[data(ignore)]
+    region_capture: bool,
+    #[data(ignore)]
+    frozen_capture: Option<CapturedImage>,
     options: Options,
     timer: f64,
     screen_index: usize,
+    screens: Vector<ScreenInfo>,
+    #[data(ignore)]
+    capture_source: Arc<dyn CaptureSource>,
+    windows: Vector<WindowInfo>,
+    #[data(ignore)]
+    window_to_capture: Option<u32>,
     #[data(ignore)]
     area_to_crop: Area,
+    presets: Vector<CapturePreset>,
+    preset: Option<usize>,
     pub canvas: Canvas,
+    color_picker: ColorPicker,
+    history: History<EditSnapshot>,
     #[data(ignore)]
     thickness: f64,
     #[data(ignore)]
@@ -98,19 +196,65 @@ pub struct AppState {
 
 impl AppState {
     pub fn new() -> Self {
+        AppState::with_capture_source(Arc::new(ScreenshotsSource::new()))
+    }
+
+    /// Create the state of the app using the given backend
+    /// to list and capture the screens
+    pub fn with_capture_source(capture_source: Arc<dyn CaptureSource>) -> Self {
+        AppState::with_options(Options::new(), capture_source)
+    }
+
+    /// Create the state of the app with the given options,
+    /// without reading them from the configuration files
+    fn with_options(options: Options, capture_source: Arc<dyn CaptureSource>) -> Self {
+        let presets = options.capture_options.get_presets().into_iter().collect();
+
         Self {
             name: format!("Screenshot App"),
             buf_view: ImageBuf::empty(),
             buf_reset: ImageBuf::empty(),
+            reset_offset: (0, 0),
             text_buffer: String::new(),
             view_state: ViewState::MainView,
             edit_state: EditState::None,
-            screenshot_token: u64::default(),
-            options: Options::new(),
+            capturing: false,
+            capture_cancel: Arc::new(AtomicBool::new(false)),
+            interval_seconds: 5.0,
+            interval_count: 0.0,
+            interval_keep_last: 0.0,
+            interval_running: false,
+            interval_frames: 0,
+            recording_fps: 10.0,
+            recording_max_seconds: 10.0,
+            recording_format: RecordingFormat::Gif,
+            recording_region: false,
+            recording_running: false,
+            recording_frames: 0,
+            scale_factor: 1.0,
+            export_resolution: ExportResolution::Physical,
+            image_origin: Option::None,
+            reset_origin: Option::None,
+            pending_origin: Option::None,
+            pending_crop: Option::None,
+            region_capture: false,
+            frozen_capture: Option::None,
+            options,
             timer: 0.0,
             screen_index: 0,
+            screens: list_screens(capture_source.as_ref())
+                .unwrap_or(vec![])
+                .into_iter()
+                .collect(),
+            capture_source,
+            windows: Vector::new(),
+            window_to_capture: Option::None,
             area_to_crop: Area::new(),
+            presets,
+            preset: Option::None,
             canvas: Canvas::new(),
+            color_picker: ColorPicker::new(Canvas::new().get_color()),
+            history: History::new(),
             thickness: 1.0,
             empty: true,
             modified: false,
@@ -143,15 +287,320 @@ impl AppState {
 
     pub fn get_buf_reset(&mut self) -> ImageBuf {
         self.modified = false;
+        self.reset_offset = (0, 0);
+        self.image_origin = self.reset_origin.clone();
         return self.buf_reset.clone();
     }
 
+    /// Return the current state of the editor, with `buf_view` as image
+    fn snapshot(&self, buf_view: ImageBuf) -> EditSnapshot {
+        return EditSnapshot {
+            buf_view,
+            modified_pixel: self.canvas.modified_pixel.clone(),
+            fills: self.canvas.fills.clone(),
+            annotations: self.canvas.annotations.clone(),
+            next_step: self.canvas.get_next_step(),
+            image_origin: self.image_origin.clone(),
+            reset_offset: self.reset_offset,
+            modified: self.modified,
+        };
+    }
+
+    fn restore_snapshot(&mut self, snapshot: EditSnapshot) {
+        self.canvas.buf_point.clear();
+        self.canvas.finish_editing();
+        self.canvas.modified_pixel = snapshot.modified_pixel;
+        self.canvas.fills = snapshot.fills;
+        self.canvas.annotations = snapshot.annotations;
+        self.canvas.set_next_step(snapshot.next_step);
+        self.canvas.select(Option::None);
+        self.image_origin = snapshot.image_origin;
+        self.reset_offset = snapshot.reset_offset;
+        self.set_buf_view(snapshot.buf_view);
+        self.modified = snapshot.modified;
+    }
+
+    /// Record the current state in the history, it has to be called before every edit of the image
+    pub fn record_edit(&mut self) {
+        let snapshot = self.snapshot(self.get_buf_view());
+        self.history.record(snapshot);
+    }
+
+    /// Restore the state before the last edit
+    pub fn undo(&mut self) {
+        let current = self.snapshot(self.get_buf_view());
+
+        if let Some(snapshot) = self.history.undo(current) {
+            self.restore_snapshot(snapshot);
+        }
+    }
+
+    /// Apply again the last undone edit
+    pub fn redo(&mut self) {
+        let current = self.snapshot(self.get_buf_view());
+
+        if let Some(snapshot) = self.history.redo(current) {
+            self.restore_snapshot(snapshot);
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        return self.history.can_undo();
+    }
+
+    pub fn can_redo(&self) -> bool {
+        return self.history.can_redo();
+    }
+
+    /// Set the colour of the next shapes, and of the selected annotation if any
+    pub fn set_color(&mut self, color: u32) {
+        self.color_picker.set_color(color);
+        self.apply_color(color);
+    }
+
+    /// Draw with `color`, and recolour the selected annotation with it
+    fn apply_color(&mut self, color: u32) {
+        let recolour = match self.canvas.get_selected_annotation() {
+            Some(annotation) => annotation.color != color,
+            Option::None => false,
+        };
+
+        if recolour {
+            self.record_edit();
+            if let Some(annotation) = self.canvas.get_selected_annotation_mut() {
+                annotation.color = color;
+            }
+        }
+
+        self.canvas.set_color(color);
+    }
+
+    pub fn delete_selected_annotation(&mut self) {
+        if self.canvas.get_selected().is_some() {
+            self.record_edit();
+            self.canvas.delete_selected();
+        }
+    }
+
+    pub fn bring_selected_to_front(&mut self) {
+        if self.canvas.get_selected().is_some() {
+            self.record_edit();
+            self.canvas.bring_to_front();
+        }
+    }
+
+    pub fn send_selected_to_back(&mut self) {
+        if self.canvas.get_selected().is_some() {
+            self.record_edit();
+            self.canvas.send_to_back();
+        }
+    }
+
+    /// Handle a click with the text tool: edit the text under `point`,
+    /// or create a new one there if no text was being edited
+    pub fn edit_text_at(&mut self, point: (i32, i32), tolerance: f64) {
+        let was_editing = self.canvas.get_editing().is_some();
+        self.canvas.finish_editing();
+
+        let index = self
+            .canvas
+            .annotation_at(point, tolerance)
+            .filter(|i| self.canvas.annotations[*i].shape == Shape::Text);
+
+        match index {
+            Some(index) => {
+                self.record_edit();
+                self.canvas.start_editing(index);
+            }
+            Option::None if !was_editing => {
+                self.record_edit();
+                self.canvas.add_annotation(Annotation::text(
+                    point,
+                    self.canvas.get_color(),
+                    self.canvas.get_font_size(),
+                    self.canvas.get_text_background(),
+                ));
+                self.canvas.start_editing(self.canvas.annotations.len() - 1);
+            }
+            Option::None => {}
+        }
+    }
+
+    /// Redact the area of the image between `start` and `end`, with the current redaction mode.
+    /// The area is redacted in the original image too, so that resetting the image doesn't reveal it
+    pub fn redact_area(&mut self, start: (i32, i32), end: (i32, i32)) {
+        self.redact_areas(&[(start, end)]);
+    }
+
+    /// Redact the areas of the image, given as (start, end) corners, as a single edit.
+    /// The areas are redacted in the original image too, so that resetting the image doesn't reveal them
+    pub fn redact_areas(&mut self, areas: &[((i32, i32), (i32, i32))]) {
+        if areas.is_empty() {
+            return;
+        }
+
+        self.record_edit();
+
+        let (width, height) = (self.buf_view.width(), self.buf_view.height());
+        let areas: Vec<((usize, usize), (usize, usize))> = areas
+            .iter()
+            .map(|(start, end)| {
+                (
+                    (start.0.max(0) as usize, start.1.max(0) as usize),
+                    (end.0.max(0) as usize, end.1.max(0) as usize),
+                )
+            })
+            .collect();
+        let new_buf = self
+            .canvas
+            .redact(self.buf_view.raw_pixels().to_vec(), width, height, &areas);
+
+        let (reset_width, reset_height) = (self.buf_reset.width(), self.buf_reset.height());
+        let mut reset_pixels = self.buf_reset.raw_pixels().to_vec();
+        let (dx, dy) = self.reset_offset;
+        let mut reset_redacted = false;
+        for (start, end) in &areas {
+            reset_redacted |= self
+                .canvas
+                .redact_pixels(
+                    &mut reset_pixels,
+                    reset_width,
+                    reset_height,
+                    (start.0 + dx, start.1 + dy),
+                    (end.0.min(width) + dx, end.1.min(height) + dy),
+                )
+                .is_some();
+        }
+        if reset_redacted {
+            self.buf_reset = ImageBuf::from_raw(
+                reset_pixels,
+                ImageFormat::RgbaSeparate,
+                reset_width,
+                reset_height,
+            );
+        }
+
+        self.set_buf_view(new_buf);
+        self.modified = true;
+    }
+
+    /// Fill with the current colour the area of similar colour under `point`
+    pub fn flood_fill_at(&mut self, point: (i32, i32)) {
+        if point.0 < 0 || point.1 < 0 {
+            return;
+        }
+
+        let snapshot = self.snapshot(self.buf_view.clone());
+        if let Some(new_buf) = self
+            .canvas
+            .flood_fill(&self.buf_view, (point.0 as usize, point.1 as usize))
+        {
+            self.history.record(snapshot);
+            self.set_buf_view(new_buf);
+            self.modified = true;
+        }
+    }
+
+    pub fn get_color_picker(&self) -> ColorPicker {
+        return self.color_picker.clone();
+    }
+
+    /// Change the colour picker and draw with its colour,
+    /// the text typed in the picker is replaced by the new colour
+    pub fn update_color_picker(&mut self, update: impl FnOnce(&mut ColorPicker)) {
+        let mut picker = self.color_picker.clone();
+        update(&mut picker);
+        if picker == self.color_picker {
+            return;
+        }
+
+        let color = picker.get_color();
+        if color != self.canvas.get_color() {
+            picker.text = format_color(color);
+            self.apply_color(color);
+        }
+        self.color_picker = picker;
+    }
+
+    /// Draw with the colour typed in the picker, as hex or RGBA.
+    /// If it isn't a valid colour the current one is shown again
+    pub fn apply_color_text(&mut self) {
+        match parse_color(&self.color_picker.text) {
+            Some(color) => self.set_color(color),
+            Option::None => self.color_picker.text = format_color(self.canvas.get_color()),
+        }
+    }
+
+    /// Draw with the colour of the pixel of the image at `point`
+    pub fn pick_color_at(&mut self, point: (i32, i32)) {
+        let (width, height) = (self.buf_view.width() as i32, self.buf_view.height() as i32);
+        if point.0 < 0 || point.1 < 0 || point.0 >= width || point.1 >= height {
+            return;
+        }
+
+        let index =
+            (point.1 * width + point.0) as usize * ImageFormat::RgbaSeparate.bytes_per_pixel();
+        let pixels = self.buf_view.raw_pixels();
+        let color = u32::from_be_bytes([
+            pixels[index],
+            pixels[index + 1],
+            pixels[index + 2],
+            pixels[index + 3],
+        ]);
+
+        self.set_color(color);
+        self.remember_color();
+    }
+
+    /// Return the colours recently used for drawing, the last one first
+    pub fn get_recent_colors(&self) -> Vec<u32> {
+        return self.options.color_options.get_recent_colors();
+    }
+
+    /// Add the current colour to the recent ones, it is called when it is used to draw
+    pub fn remember_color(&mut self) {
+        let color = self.canvas.get_color();
+        self.options.color_options.add_recent_color(color);
+    }
+
+    /// Place a step marker centered on `point`, numbered after the previous one
+    pub fn add_step_at(&mut self, point: (i32, i32)) {
+        self.record_edit();
+
+        let step = self.canvas.new_step(point, self.thickness as usize);
+        self.canvas.add_annotation(step);
+        self.remember_color();
+    }
+
+    /// Return the image with the annotations drawn on it, to be saved or copied
+    pub fn get_flattened_buf(&self) -> ImageBuf {
+        if self.canvas.annotations.is_empty() {
+            return self.buf_view.clone();
+        }
+
+        let (width, height) = (self.buf_view.width(), self.buf_view.height());
+        let pixels = flatten_annotations(
+            self.canvas.annotations.iter(),
+            self.buf_view.raw_pixels().to_vec(),
+            width,
+            height,
+        );
+
+        return ImageBuf::from_raw(pixels, ImageFormat::RgbaSeparate, width, height);
+    }
+
+    /// Copy the image with its annotations on the clipboard.
+    /// Nothing is copied if the clipboard is not available (e.g. there is no display)
     pub fn copy_to_clipboard(&self) {
-        let mut clipboard = Clipboard::new().unwrap();
+        let mut clipboard = match Clipboard::new() {
+            Ok(clipboard) => clipboard,
+            Err(_) => return,
+        };
+        let buf = self.get_flattened_buf();
         let img = ImageData {
-            width: self.buf_view.width() as usize,
-            height: self.buf_view.height() as usize,
-            bytes: Cow::from(self.buf_view.clone().raw_pixels().to_vec()),
+            width: buf.width() as usize,
+            height: buf.height() as usize,
+            bytes: Cow::from(buf.raw_pixels().to_vec()),
         };
 
         clipboard
@@ -159,6 +608,80 @@ impl AppState {
             .expect("Unable to copy image on clipboard");
     }
 
+    /// Ask the user for an image file (PNG, JPEG, GIF or BMP)
+    /// and load it in the editor, so that it can be annotated
+    pub fn open_image(&mut self) {
+        let path = match FileDialog::new()
+            .set_location(&self.get_save_path_for_view())
+            .add_filter("Image", &["png", "jpg", "jpeg", "gif", "bmp"])
+            .show_open_single_file()
+        {
+            Ok(Some(path)) => path,
+            Ok(Option::None) => return,
+            Err(e) => {
+                show_message(
+                    "Unable to open image",
+                    format!("{}\nThe current image has been kept", e),
+                    native_dialog::MessageType::Error,
+                );
+                return;
+            }
+        };
+
+        match image::open(&path) {
+            Ok(img) => {
+                let img = img.to_rgba8();
+                let (width, height) = (img.width() as usize, img.height() as usize);
+
+                self.load_image(ImageBuf::from_raw(
+                    img.into_raw(),
+                    ImageFormat::RgbaSeparate,
+                    width,
+                    height,
+                ));
+            }
+            Err(e) => show_message(
+                "Unable to open image",
+                format!("{}: {}", path.display(), e),
+                native_dialog::MessageType::Error,
+            ),
+        }
+    }
+
+    /// Load the image in the clipboard in the editor, so that it can be annotated
+    pub fn paste_image(&mut self) {
+        let img = match Clipboard::new().and_then(|mut clipboard| clipboard.get_image()) {
+            Ok(img) => img,
+            Err(e) => {
+                show_message(
+                    "Unable to paste image",
+                    format!("The clipboard does not contain an image: {}", e),
+                    native_dialog::MessageType::Warning,
+                );
+                return;
+            }
+        };
+
+        self.load_image(ImageBuf::from_raw(
+            img.bytes.into_owned(),
+            ImageFormat::RgbaSeparate,
+            img.width,
+            img.height,
+        ));
+    }
+
+    /// Load an image that does not come from a capture in the editor
+    fn load_image(&mut self, image: ImageBuf) {
+        self.set_edit_state(EditState::None);
+        self.load_capture(
+            CapturedImage {
+                image,
+                scale_factor: 1.0,
+            },
+            Option::None,
+        );
+    }
+
     pub fn get_save_path_for_save(&mut self) -> PathBuf {
         return self.options.save_path.get_save_path_for_save().clone();
     }
@@ -167,6 +690,14 @@ impl AppState {
         return self.options.save_path.get_save_path_for_view().clone();
     }
 
+    pub fn get_include_cursor(&self) -> bool {
+        return self.options.capture_options.get_include_cursor();
+    }
+
+    pub fn set_include_cursor(&mut self, include_cursor: bool) {
+        self.options.capture_options.set_include_cursor(include_cursor);
+    }
+
     pub fn get_extension(&self) -> String {
         return self.options.extension.clone();
     }
@@ -199,12 +730,616 @@ impl AppState {
         self.edit_state.clone()
     }
 
-    pub fn set_screenshot_token(&mut self, token: u64) {
-        self.screenshot_token = token;
+    pub fn is_capturing(&self) -> bool {
+        self.capturing
+    }
+
+    /// Start a new screenshot on a worker thread, so that the UI is not blocked
+    /// during the delay. When the delay is over the worker asks to minimize the window,
+    /// then it captures the selected screen (or window) and it sends back the result
+    /// with the `screenshot_done` command. A pending screenshot is cancelled.
+    pub fn start_screenshot(&mut self, sink: impl CaptureSink) {
+        let target = match self.window_to_capture.take() {
+            Some(window_id) => CaptureTarget::Window(window_id),
+            Option::None => CaptureTarget::Screen(self.screen_index),
+        };
+
+        self.start_capture(sink, target);
+    }
+
+    /// Start a new screenshot of the screen the last region has been cropped from,
+    /// the capture is cropped straight to that region without passing from the editor crop
+    pub fn start_last_region_screenshot(&mut self, sink: impl CaptureSink) {
+        let (screen_index, area) = match self.get_last_region() {
+            Some(last_region) => last_region,
+            Option::None => {
+                thread::spawn(move || {
+                    MessageDialog::new()
+                        .set_title("Unable to capture the last region")
+                        .set_text("No region has been cropped yet")
+                        .set_type(native_dialog::MessageType::Warning)
+                        .show_alert()
+                        .unwrap();
+                });
+                return;
+            }
+        };
+
+        self.start_capture(sink, CaptureTarget::Screen(screen_index));
+        self.pending_crop = Some(area);
+    }
+
+    fn start_capture(&mut self, sink: impl CaptureSink, target: CaptureTarget) {
+        self.cancel_screenshot();
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.capture_cancel = cancelled.clone();
+        self.capturing = true;
+        self.region_capture = false;
+        self.pending_crop = Option::None;
+
+        let capture_source = self.capture_source.clone();
+        let delay_ms = self.get_delay_ms();
+        let include_cursor = self.get_include_cursor();
+        self.pending_origin = match target {
+            CaptureTarget::Screen(screen_index) => Some(ImageOrigin {
+                screen_index,
+                left_corner: (0, 0),
+            }),
+            CaptureTarget::Window(_) => Option::None,
+        };
+
+        thread::spawn(move || {
+            if wait_with_cancel(delay_ms, &cancelled).is_err() {
+                return;
+            }
+
+            sink.send(CaptureMessage::Minimize);
+
+            let result = take_screenshot_with_delay(
+                capture_source.as_ref(),
+                MINIMIZE_DELAY_MS,
+                &target,
+                include_cursor,
+                &cancelled,
+            );
+
+            // the window has already been minimized, so it is restored without a capture
+            if let Err(CaptureError::Cancelled) = result {
+                sink.send(CaptureMessage::Restore);
+                return;
+            }
+
+            sink.send(CaptureMessage::Done(result));
+        });
+    }
+
+    /// Start a new screenshot like `start_screenshot`, but instead of loading it
+    /// in the editor the capture is kept frozen, so that the user can select
+    /// the region to keep on a fullscreen overlay
+    pub fn start_region_screenshot(&mut self, sink: impl CaptureSink) {
+        self.start_screenshot(sink);
+        self.region_capture = true;
+    }
+
+    /// Return the capture shown on the region selection overlay, if any
+    pub fn get_frozen_capture(&self) -> Option<CapturedImage> {
+        self.frozen_capture.clone()
+    }
+
+    /// Return the rect `(x, y, width, height)` of the selected screen
+    /// in logical coordinates, that is where the region overlay has to be placed
+    pub fn get_region_bounds(&self) -> (i32, i32, u32, u32) {
+        if self.screen_index == ALL_SCREENS {
+            return virtual_desktop_bounds(self.capture_source.as_ref()).unwrap_or((0, 0, 0, 0));
+        }
+
+        match self.screens.get(self.screen_index) {
+            Some(screen) => (screen.x, screen.y, screen.width, screen.height),
+            Option::None => (0, 0, 0, 0),
+        }
+    }
+
+    /// Handle the end of the region selection: the selected area of the frozen
+    /// capture is loaded in the editor, if the selection has been cancelled
+    /// the current image is kept
+    pub fn finish_region_selection(&mut self, area: Option<Area>) {
+        let captured = match self.frozen_capture.take() {
+            Some(captured) => captured,
+            Option::None => return,
+        };
+        let origin = self.pending_origin.take();
+
+        let area = match area {
+            Some(area) => area,
+            Option::None => return,
+        };
+
+        match crop_image(&captured.image, &area) {
+            Ok(image) => {
+                self.load_capture(
+                    CapturedImage {
+                        image,
+                        scale_factor: captured.scale_factor,
+                    },
+                    origin,
+                );
+                self.remember_region(&area);
+                // the region is the whole image, a reset keeps it in its place
+                self.reset_origin = self.image_origin.clone();
+            }
+            Err(err) => show_capture_error(err),
+        }
+    }
+
+    /// Remember the area cropped from the current image as the last region,
+    /// if the image comes from a screen. The region is persisted, so that
+    /// it can be captured again with `start_last_region_screenshot`
+    fn remember_region(&mut self, area: &Area) {
+        let origin = match &self.image_origin {
+            Some(origin) => origin.clone(),
+            Option::None => return,
+        };
+        let left_corner = (
+            origin.left_corner.0 + area.left_corner.0,
+            origin.left_corner.1 + area.left_corner.1,
+        );
+
+        self.image_origin = Some(ImageOrigin {
+            screen_index: origin.screen_index,
+            left_corner,
+        });
+        self.options.capture_options.set_last_region(StoredRegion {
+            all_screens: origin.screen_index == ALL_SCREENS,
+            screen_index: match origin.screen_index {
+                ALL_SCREENS => 0,
+                screen_index => screen_index,
+            },
+            x: left_corner.0,
+            y: left_corner.1,
+            width: area.width,
+            height: area.height,
+        });
+    }
+
+    pub fn get_presets(&self) -> Vector<CapturePreset> {
+        self.presets.clone()
+    }
+
+    /// Update the presets of the selection, they are persisted only if they have changed
+    pub fn set_presets(&mut self, presets: Vector<CapturePreset>) {
+        if presets == self.presets {
+            return;
+        }
+
+        self.presets = presets;
+        self.options
+            .capture_options
+            .set_presets(self.presets.iter().cloned().collect());
+    }
+
+    pub fn add_preset(&mut self) {
+        let mut presets = self.get_presets();
+        presets.push_back(CapturePreset::new("New preset", 1280, 720, true));
+        self.set_presets(presets);
+    }
+
+    /// Remove the preset with the given index, the selected preset is kept
+    pub fn remove_preset(&mut self, index: usize) {
+        if index >= self.presets.len() {
+            return;
+        }
+
+        let mut presets = self.get_presets();
+        presets.remove(index);
+        self.set_presets(presets);
+
+        self.preset = match self.preset {
+            Some(preset) if preset == index => Option::None,
+            Some(preset) if preset > index => Some(preset - 1),
+            preset => preset,
+        };
+    }
+
+    /// Return the constraint of the selected preset, that the selection of an area has to respect
+    pub fn get_area_constraint(&self) -> AreaConstraint {
+        let preset = match self.preset.and_then(|index| self.presets.get(index)) {
+            Some(preset) => preset,
+            Option::None => return AreaConstraint::Free,
+        };
+
+        if preset.width == 0 || preset.height == 0 {
+            return AreaConstraint::Free;
+        }
+
+        match preset.fixed_size {
+            true => AreaConstraint::FixedSize(preset.width, preset.height),
+            false => AreaConstraint::AspectRatio(preset.width, preset.height),
+        }
+    }
+
+    /// Return the screen index and the area of the last region cropped from a screen
+    pub fn get_last_region(&self) -> Option<(usize, Area)> {
+        let last_region = self.options.capture_options.get_last_region()?;
+        let screen_index = match last_region.all_screens {
+            true => ALL_SCREENS,
+            false => last_region.screen_index,
+        };
+
+        return Some((
+            screen_index,
+            Area {
+                left_corner: (last_region.x, last_region.y),
+                width: last_region.width,
+                height: last_region.height,
+            },
+        ));
+    }
+
+    /// Load a new capture in the editor, `origin` is the position of the
+    /// image in the captured screen, if it comes from a screen
+    fn load_capture(&mut self, captured: CapturedImage, origin: Option<ImageOrigin>) {
+        self.reset_img();
+        // the pixels modified on the previous image can not be restored on the new one
+        self.canvas.clear_modified_pixels();
+        self.canvas.clear_annotations();
+        self.history.clear();
+        self.scale_factor = captured.scale_factor;
+        self.image_origin = origin.clone();
+        self.reset_origin = origin;
+        self.set_buf_view(captured.image.clone());
+        self.set_buf_reset(captured.image);
+        self.reset_offset = (0, 0);
     }
 
-    pub fn get_screenshot_token(&mut self) -> u64 {
-        return self.screenshot_token;
+    /// Cancel the pending screenshot, if any
+    pub fn cancel_screenshot(&mut self) {
+        self.capture_cancel.store(true, Ordering::SeqCst);
+        self.capturing = false;
+    }
+
+    /// Return true if a screenshot, an interval capture or a recording is running
+    pub fn is_capture_running(&self) -> bool {
+        self.is_capturing() || self.is_interval_running() || self.is_recording_running()
+    }
+
+    /// Stop the running capture: the interval capture, the recording or the pending screenshot
+    pub fn stop_capture(&mut self) {
+        if self.is_interval_running() {
+            self.stop_interval_capture();
+        } else if self.is_recording_running() {
+            self.stop_recording();
+        } else {
+            self.cancel_screenshot();
+        }
+    }
+
+    pub fn is_interval_running(&self) -> bool {
+        self.interval_running
+    }
+
+    pub fn get_interval_frames(&self) -> u32 {
+        self.interval_frames
+    }
+
+    /// Return the settings of the interval capture selected by the user,
+    /// a count or a number of files to keep equal to 0 means no limit
+    pub fn get_interval_settings(&self) -> IntervalSettings {
+        IntervalSettings {
+            interval_ms: (self.interval_seconds * 1000.0).round() as u64,
+            count: match self.interval_count.round() as u32 {
+                0 => Option::None,
+                count => Some(count),
+            },
+            keep_last: match self.interval_keep_last.round() as u32 {
+                0 => Option::None,
+                keep_last => Some(keep_last),
+            },
+            include_cursor: self.get_include_cursor(),
+        }
+    }
+
+    /// Start an interval capture on a worker thread: the selected screen is captured
+    /// every `interval_seconds` and each frame is saved straight to the save path,
+    /// without loading it in the editor. The progress is sent back with the
+    /// `interval_progress` command and the end with the `interval_done` command.
+    pub fn start_interval_capture(&mut self, sink: ExtEventSink) {
+        self.cancel_screenshot();
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.capture_cancel = cancelled.clone();
+        self.interval_running = true;
+        self.interval_frames = 0;
+
+        let capture_source = self.capture_source.clone();
+        let target = CaptureTarget::Screen(self.screen_index);
+        let settings = self.get_interval_settings();
+        let dir = self.get_save_path_for_save();
+        let extension = self.get_extension();
+        let base_name = format!("interval {}", Local::now().format("%y-%m-%d %H%M%S"));
+
+        thread::spawn(move || {
+            sink.submit_command(Selector::new("minimize"), (), Target::Auto)
+                .expect("Unable to submit a command");
+
+            let result = match wait_with_cancel(MINIMIZE_DELAY_MS, &cancelled) {
+                Ok(_) => capture_interval(
+                    capture_source.as_ref(),
+                    &target,
+                    &settings,
+                    &dir,
+                    &base_name,
+                    &extension,
+                    &cancelled,
+                    |frames| {
+                        sink.submit_command(
+                            Selector::<u32>::new("interval_progress"),
+                            frames,
+                            Target::Auto,
+                        )
+                        .expect("Unable to submit a command");
+                    },
+                ),
+                Err(_) => IntervalReport::default(),
+            };
+
+            sink.submit_command(
+                Selector::<IntervalReport>::new("interval_done"),
+                result,
+                Target::Auto,
+            )
+            .expect("Unable to submit a command");
+        });
+    }
+
+    /// Stop the running interval capture, the frames saved so far are kept.
+    /// The worker ends with the `interval_done` command
+    pub fn stop_interval_capture(&mut self) {
+        self.capture_cancel.store(true, Ordering::SeqCst);
+    }
+
+    /// Handle the end of an interval capture, telling the user how many frames
+    /// have been skipped because they could not be captured or saved, if any
+    pub fn finish_interval_capture(&mut self, report: IntervalReport) {
+        self.interval_running = false;
+
+        if let Some(err) = report.last_error {
+            let text = format!(
+                "{} of {} frames could not be saved\n{}",
+                report.failed,
+                report.saved + report.failed,
+                err
+            );
+
+            thread::spawn(move || {
+                MessageDialog::new()
+                    .set_title("Interval capture completed with errors")
+                    .set_text(&text)
+                    .set_type(native_dialog::MessageType::Error)
+                    .show_alert()
+                    .unwrap();
+            });
+        }
+    }
+
+    /// Return the scale factor of the screen the current image has been captured from
+    pub fn get_scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Return the image to save, at the resolution selected by the user
+    fn get_export_buffer(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let flattened = self.get_flattened_buf();
+        let buf = match self.export_resolution {
+            ExportResolution::Physical => flattened,
+            ExportResolution::Logical => to_logical_resolution(&flattened, self.scale_factor),
+        };
+
+        return ImageBuffer::from_raw(
+            buf.width() as u32,
+            buf.height() as u32,
+            buf.raw_pixels().to_vec(),
+        )
+        .unwrap();
+    }
+
+    pub fn is_recording_running(&self) -> bool {
+        self.recording_running
+    }
+
+    pub fn get_recording_frames(&self) -> u32 {
+        self.recording_frames
+    }
+
+    /// Return true if a region has been cropped from a screen capture,
+    /// so that it can be used to record only that region
+    pub fn has_last_region(&self) -> bool {
+        self.get_last_region().is_some()
+    }
+
+    /// Return the settings of the recording selected by the user
+    pub fn get_recording_settings(&self) -> RecordingSettings {
+        RecordingSettings {
+            fps: self.recording_fps.round().max(1.0) as u32,
+            max_duration_ms: (self.recording_max_seconds * 1000.0).round() as u64,
+            area: match self.recording_region {
+                true => self.get_last_region().map(|(_, area)| area),
+                false => Option::None,
+            },
+            include_cursor: self.get_include_cursor(),
+        }
+    }
+
+    /// Start a recording on a worker thread: the selected screen (or the last cropped
+    /// region of it) is captured `recording_fps` times per second until the maximum
+    /// duration is reached or the recording is stopped, then the frames are encoded
+    /// in an animated file in the save path. The progress is sent back with the
+    /// `recording_progress` command and the end with the `recording_done` command.
+    pub fn start_recording(&mut self, sink: ExtEventSink) {
+        self.cancel_screenshot();
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.capture_cancel = cancelled.clone();
+        self.recording_running = true;
+        self.recording_frames = 0;
+
+        let capture_source = self.capture_source.clone();
+        // the last region is recorded on the screen it has been cropped from
+        let target = match (self.recording_region, self.get_last_region()) {
+            (true, Some((screen_index, _))) => CaptureTarget::Screen(screen_index),
+            _ => CaptureTarget::Screen(self.screen_index),
+        };
+        let settings = self.get_recording_settings();
+        let format = self.recording_format;
+        let mut path = self.get_save_path_for_save();
+        path.push(format!("recording {}", Local::now().format("%y-%m-%d %H%M%S")));
+        path.set_extension(format.extension());
+
+        thread::spawn(move || {
+            sink.submit_command(Selector::new("minimize"), (), Target::Auto)
+                .expect("Unable to submit a command");
+
+            let result = match wait_with_cancel(MINIMIZE_DELAY_MS, &cancelled) {
+                Ok(_) => record_to_file(
+                    capture_source.as_ref(),
+                    &target,
+                    &settings,
+                    format,
+                    &path,
+                    &cancelled,
+                    |frames| {
+                        sink.submit_command(
+                            Selector::<u32>::new("recording_progress"),
+                            frames,
+                            Target::Auto,
+                        )
+                        .expect("Unable to submit a command");
+                    },
+                )
+                .map(|_| path),
+                Err(err) => Err(err),
+            };
+
+            sink.submit_command(
+                Selector::<Result<PathBuf, CaptureError>>::new("recording_done"),
+                result,
+                Target::Auto,
+            )
+            .expect("Unable to submit a command");
+        });
+    }
+
+    /// Stop the running recording, the worker ends with the `recording_done` command
+    pub fn stop_recording(&mut self) {
+        self.capture_cancel.store(true, Ordering::SeqCst);
+    }
+
+    /// Handle the end of a recording, showing the error to the user if any
+    pub fn finish_recording(&mut self, result: Result<PathBuf, CaptureError>) {
+        self.recording_running = false;
+
+        match result {
+            Ok(_) | Err(CaptureError::Cancelled) => (),
+            Err(err) => {
+                thread::spawn(move || {
+                    MessageDialog::new()
+                        .set_title("Recording failed")
+                        .set_text(&err.to_string())
+                        .set_type(native_dialog::MessageType::Error)
+                        .show_alert()
+                        .unwrap();
+                });
+            }
+        }
+    }
+
+    /// Handle the result of a screenshot taken by the worker thread:
+    /// on success the new image is loaded in the editor, otherwise
+    /// the error is shown to the user and the current image is kept
+    pub fn finish_screenshot(&mut self, result: Result<CapturedImage, CaptureError>) {
+        self.capturing = false;
+
+        match result {
+            Ok(captured) => {
+                if self.region_capture {
+                    self.frozen_capture = Some(captured);
+                } else if let Some(area) = self.pending_crop.take() {
+                    self.finish_last_region_screenshot(captured, area);
+                } else {
+                    let origin = self.pending_origin.take();
+                    self.load_capture(captured, origin);
+                }
+            }
+            Err(err) => {
+                if let CaptureError::ScreenNotFound(_) = err {
+                    self.set_screen_index(0);
+                }
+
+                show_capture_error(err);
+            }
+        }
+    }
+
+    /// Handle the `screenshot_done` command, unless the screenshot has been cancelled
+    /// in the meantime, and return the command that brings the window back: the region
+    /// overlay when there is a frozen capture to select, the main window otherwise
+    pub fn handle_screenshot_done(
+        &mut self,
+        result: Result<CapturedImage, CaptureError>,
+    ) -> Selector {
+        if self.is_capturing() {
+            self.finish_screenshot(result);
+        }
+
+        // the main window is restored when the region has been selected
+        if self.get_frozen_capture().is_some() {
+            return Selector::new("open_region_overlay");
+        }
+
+        return Selector::new("restore");
+    }
+
+    /// Crop the capture of the screen to the last region and load it in the editor.
+    /// If the region does not fit the screen anymore (e.g. the resolution has changed)
+    /// the user is told and the current image is kept
+    fn finish_last_region_screenshot(&mut self, captured: CapturedImage, area: Area) {
+        let origin = self.pending_origin.take();
+
+        match crop_image(&captured.image, &area) {
+            Ok(image) => {
+                self.load_capture(
+                    CapturedImage {
+                        image,
+                        scale_factor: captured.scale_factor,
+                    },
+                    origin,
+                );
+                self.remember_region(&area);
+                // the region is the whole image, a reset keeps it in its place
+                self.reset_origin = self.image_origin.clone();
+            }
+            Err(err) => {
+                let (width, height) = (captured.image.width(), captured.image.height());
+
+                thread::spawn(move || {
+                    MessageDialog::new()
+                        .set_title("Unable to capture the last region")
+                        .set_text(&format!(
+                            "{}\nThe screen is now {}x{} pixels, crop a new region to replace it.\nThe current image has been kept",
+                            err, width, height
+                        ))
+                        .set_type(native_dialog::MessageType::Error)
+                        .show_alert()
+                        .unwrap();
+                });
+            }
+        }
+    }
+
+    /// Return the delay of the timer in milliseconds, without the time
+    /// needed to minimize the window that is always waited before the capture
+    pub fn get_delay_ms(&self) -> u64 {
+        ((self.timer * 1000.0).round() as u64).saturating_sub(MINIMIZE_DELAY_MS)
     }
 
     pub fn set_timer(&mut self, timer: f64) {
@@ -212,7 +1347,6 @@ impl AppState {
     }
 
     pub fn get_timer(&self) -> f64 {
-        println!("{}", self.timer);
         self.timer
     }
 
@@ -232,6 +1366,51 @@ impl AppState {
         self.screen_index
     }
 
+    pub fn get_screen_count(&self) -> usize {
+        self.screens.len()
+    }
+
+    pub fn get_screens(&self) -> Vector<ScreenInfo> {
+        self.screens.clone()
+    }
+
+    /// Update the list of the connected screens, if the selected screen
+    /// has been disconnected the first one is selected
+    pub fn update_screens(&mut self, screens: Vector<ScreenInfo>) {
+        if self.screen_index != ALL_SCREENS && self.screen_index >= screens.len() {
+            self.set_screen_index(0);
+        }
+
+        self.screens = screens;
+    }
+
+    pub fn get_capture_source(&self) -> Arc<dyn CaptureSource> {
+        self.capture_source.clone()
+    }
+
+    /// Retrieve the list of windows that can be captured,
+    /// excluding the window of this app
+    pub fn load_windows(&mut self) -> Result<(), CaptureError> {
+        let name = self.get_name();
+
+        self.windows = list_windows(self.capture_source.as_ref())?
+            .into_iter()
+            .filter(|w| w.title != name)
+            .collect();
+
+        return Ok(());
+    }
+
+    pub fn get_windows(&self) -> Vector<WindowInfo> {
+        self.windows.clone()
+    }
+
+    /// Select the window to capture with the next screenshot,
+    /// `None` means that the whole screen has to be captured
+    pub fn set_window_to_capture(&mut self, window_id: Option<u32>) {
+        self.window_to_capture = window_id;
+    }
+
     pub fn get_text_buffer(&self) -> String {
         self.text_buffer.clone()
     }
@@ -244,7 +1423,7 @@ impl AppState {
         if self.modified {
             return true;
         } else {
-            if self.canvas.modified_pixel.is_empty() {
+            if !self.canvas.has_modified_pixels() && self.canvas.annotations.is_empty() {
                 return false;
             } else {
                 return true;
@@ -275,41 +1454,35 @@ impl AppState {
         self.area_to_crop = area;
     }
 
-    pub fn clear_highlight(&mut self) {
+    /// Return the image without the highlight of the area to crop
+    fn get_unhighlighted_buf(&self) -> ImageBuf {
         let (width, height) = (self.buf_view.width(), self.buf_view.height());
         let mut container = self.buf_view.raw_pixels().to_vec();
         for i in (0..container.len()).step_by(4) {
             container[(i + 3) as usize] = 255;
         }
-        let new_buf_view = ImageBuf::from_raw(
+        return ImageBuf::from_raw(
             container,
             druid::piet::ImageFormat::RgbaSeparate,
             width as usize,
             height as usize,
         );
+    }
+
+    pub fn clear_highlight(&mut self) {
+        let new_buf_view = self.get_unhighlighted_buf();
         self.set_buf_view(new_buf_view);
     }
 
     pub fn highlight_area(&mut self, start_point: (i32, i32), end_point: (i32, i32)) {
         let img_size = (self.buf_view.width() as u32, self.buf_view.height() as u32);
+        let constraint = self.get_area_constraint();
 
-        let mut start_point_cut = (0, 0);
-        start_point_cut.0 = if start_point.0 > end_point.0 {
-            end_point.0 as usize
-        } else {
-            start_point.0 as usize
-        };
-        start_point_cut.1 = if start_point.1 > end_point.1 {
-            end_point.1 as usize
-        } else {
-            start_point.1 as usize
-        };
-
-        self.canvas.start_point_cut = start_point_cut;
-
-        match calculate_area(img_size, start_point, end_point) {
+        match calculate_constrained_area(img_size, start_point, end_point, constraint) {
             Option::Some(area) => {
                 let (offset_c, offset_r) = area.left_corner;
+                // the area can differ from the dragged rect when a preset is selected
+                self.canvas.start_point_cut = (offset_c as usize, offset_r as usize);
                 let mut container = self.buf_view.raw_pixels().to_vec();
 
                 for i in (0..container.len()).step_by(4) {
@@ -336,6 +1509,10 @@ impl AppState {
     }
 
     pub fn resize_img(&mut self) {
+        // the whole image is recorded, so that undoing the crop restores it with its annotations
+        let snapshot = self.snapshot(self.get_unhighlighted_buf());
+        self.history.record(snapshot);
+
         let img_size = (self.buf_view.width() as u32, self.buf_view.height() as u32);
         let (offset_c, offset_r) = self.area_to_crop.left_corner;
         let width = self.area_to_crop.width;
@@ -365,8 +1542,16 @@ impl AppState {
 
         let old_width = self.buf_view.width();
 
+        // remember the cropped region of the screen, it can be captured again later
+        let area_to_crop = self.area_to_crop.clone();
+        self.remember_region(&area_to_crop);
+
         self.set_buf_view(new_buf_view);
         self.modified = true;
+        self.reset_offset = (
+            self.reset_offset.0 + offset_c as usize,
+            self.reset_offset.1 + offset_r as usize,
+        );
 
         let mut new_modified_pixel = HashMap::new();
         self.canvas.modified_pixel.iter().for_each(|e| {
@@ -404,17 +1589,14 @@ impl AppState {
         });
 
         self.canvas.modified_pixel = new_modified_pixel;
+        self.canvas.crop_fills(self.canvas.start_point_cut);
+        self.canvas.translate_annotations(-(offset_c as i32), -(offset_r as i32));
     }
 
     pub fn save_img(&mut self) {
         let mut path = self.get_save_path_for_save();
         let extension = self.get_extension();
-        let img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_raw(
-            self.buf_view.width() as u32,
-            self.buf_view.height() as u32,
-            self.buf_view.clone().raw_pixels().to_vec(),
-        )
-        .unwrap();
+        let img = self.get_export_buffer();
 
         if img.is_empty() {
             MessageDialog::new()
@@ -452,12 +1634,7 @@ impl AppState {
     pub fn save_img_as(&mut self, ctx: ExtEventSink) {
         let default_file_name = format!("image {}", Local::now().format("%y-%m-%d %H%M%S")); //name from timestamp
         let path = self.get_save_path_for_save();
-        let img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_raw(
-            self.buf_view.width() as u32,
-            self.buf_view.height() as u32,
-            self.buf_view.clone().raw_pixels().to_vec(),
-        )
-        .unwrap();
+        let img = self.get_export_buffer();
 
         if img.is_empty() {
             MessageDialog::new()
@@ -505,6 +1682,97 @@ impl AppState {
     }
 }
 
+/// Message sent by the worker thread of a screenshot
+pub enum CaptureMessage {
+    /// The delay is over, the window has to be minimized before the capture
+    Minimize,
+    /// The screenshot has been cancelled after the window has been minimized
+    Restore,
+    /// The result of the capture
+    Done(Result<CapturedImage, CaptureError>),
+}
+
+/// Receiver of the messages of the screenshot worker thread. The app uses its
+/// `ExtEventSink`, that turns every message into the matching command
+pub trait CaptureSink: Send + 'static {
+    fn send(&self, message: CaptureMessage);
+}
+
+impl CaptureSink for ExtEventSink {
+    fn send(&self, message: CaptureMessage) {
+        match message {
+            CaptureMessage::Minimize => {
+                self.submit_command(Selector::new("minimize"), (), Target::Auto)
+            }
+            CaptureMessage::Restore => {
+                self.submit_command(Selector::new("restore"), (), Target::Auto)
+            }
+            CaptureMessage::Done(result) => self.submit_command(
+                Selector::<Result<CapturedImage, CaptureError>>::new("screenshot_done"),
+                result,
+                Target::Auto,
+            ),
+        }
+        .expect("Unable to submit a command");
+    }
+}
+
+/// Show a message to the user on its own thread, so that the event loop is not blocked
+fn show_message(title: &'static str, text: String, kind: native_dialog::MessageType) {
+    thread::spawn(move || {
+        MessageDialog::new()
+            .set_title(title)
+            .set_text(&text)
+            .set_type(kind)
+            .show_alert()
+            .unwrap();
+    });
+}
+
+/// Show the error of a failed capture to the user, telling that the current image is kept
+fn show_capture_error(err: CaptureError) {
+    show_message(
+        "Unable to take screenshot",
+        format!("{}\nThe current image has been kept", err),
+        native_dialog::MessageType::Error,
+    );
+}
+
+/// This function starts a thread that checks the connected screens every
+/// `SCREEN_POLL_MS` milliseconds and sends the new list with the `screens_changed`
+/// command when a monitor is plugged or unplugged. The thread ends with the app
+pub fn watch_screens(capture_source: Arc<dyn CaptureSource>, sink: ExtEventSink) {
+    thread::spawn(move || {
+        let mut last_screens = list_screens(capture_source.as_ref()).unwrap_or(vec![]);
+
+        loop {
+            thread::sleep(Duration::from_millis(SCREEN_POLL_MS));
+
+            let screens = match list_screens(capture_source.as_ref()) {
+                Ok(screens) => screens,
+                Err(_) => continue,
+            };
+
+            if screens == last_screens {
+                continue;
+            }
+
+            last_screens = screens.clone();
+
+            if sink
+                .submit_command(
+                    Selector::<Vector<ScreenInfo>>::new("screens_changed"),
+                    screens.into_iter().collect::<Vector<ScreenInfo>>(),
+                    Target::Auto,
+                )
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+}
+
 #[derive(Debug)]
 pub struct EventHandler {
     keys_pressed: Vector<druid::keyboard_types::Key>,
@@ -523,36 +1791,71 @@ impl EventHandler {
 }
 
 impl AppDelegate<AppState> for EventHandler {
-    fn event(
+    fn command(
         &mut self,
         ctx: &mut DelegateCtx,
-        _window_id: druid::WindowId,
-        event: druid::Event,
+        _target: Target,
+        cmd: &Command,
         data: &mut AppState,
         _env: &Env,
-    ) -> Option<druid::Event> {
-        match event {
-            Event::Timer(ref timer_event) => {
-                if data.get_screenshot_token() == timer_event.into_raw() {
-                    if data.get_screen_index() > (number_of_screens() - 1) {
-                        data.set_screen_index(0);
-                    }
+    ) -> Handled {
+        if let Some(result) = cmd.get(Selector::<Result<CapturedImage, CaptureError>>::new(
+            "screenshot_done",
+        )) {
+            let next = data.handle_screenshot_done(result.clone());
+            ctx.submit_command(Command::new(next, (), Target::Auto));
+
+            return Handled::Yes;
+        }
 
-                    let img =
-                        take_screenshot_with_delay(data.timer, data.get_screen_index()).unwrap();
+        if let Some(frames) = cmd.get(Selector::<u32>::new("interval_progress")) {
+            data.interval_frames = *frames;
 
-                    data.set_buf_view(img.clone());
-                    data.set_buf_reset(img);
+            return Handled::Yes;
+        }
 
-                    ctx.submit_command(Command::new(Selector::new("restore"), (), Target::Auto));
+        if let Some(report) = cmd.get(Selector::<IntervalReport>::new("interval_done")) {
+            data.finish_interval_capture(report.clone());
 
-                    data.set_screenshot_token(u64::MAX);
+            ctx.submit_command(Command::new(Selector::new("restore"), (), Target::Auto));
 
-                    return Some(event);
-                } else {
-                    return Some(event);
-                }
-            }
+            return Handled::Yes;
+        }
+
+        if let Some(screens) = cmd.get(Selector::<Vector<ScreenInfo>>::new("screens_changed")) {
+            data.update_screens(screens.clone());
+
+            return Handled::Yes;
+        }
+
+        if let Some(frames) = cmd.get(Selector::<u32>::new("recording_progress")) {
+            data.recording_frames = *frames;
+
+            return Handled::Yes;
+        }
+
+        if let Some(result) =
+            cmd.get(Selector::<Result<PathBuf, CaptureError>>::new("recording_done"))
+        {
+            data.finish_recording(result.clone());
+
+            ctx.submit_command(Command::new(Selector::new("restore"), (), Target::Auto));
+
+            return Handled::Yes;
+        }
+
+        return Handled::No;
+    }
+
+    fn event(
+        &mut self,
+        ctx: &mut DelegateCtx,
+        _window_id: druid::WindowId,
+        event: druid::Event,
+        data: &mut AppState,
+        _env: &Env,
+    ) -> Option<druid::Event> {
+        match event {
             Event::MouseDown(ref mouse_event) => {
                 if data.get_edit_state() == MouseDetecting {
                     let start_point: (i32, i32) = (
@@ -596,6 +1899,11 @@ impl AppDelegate<AppState> for EventHandler {
                             })
                             .collect();
                     }
+                } else if data.get_edit_state() == Drawing
+                    && data.canvas.get_editing().is_none()
+                    && (key_event.key == Key::Delete || key_event.key == Key::Backspace)
+                {
+                    data.delete_selected_annotation();
                 }
                 return Some(event);
             }
@@ -629,3 +1937,126 @@ impl AppDelegate<AppState> for EventHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshot_lib::source::source::screens_dir;
+    use std::{fs, sync::mpsc, time::Instant};
+
+    fn state_with_screens(dir: &PathBuf) -> AppState {
+        let options = Options {
+            capture_options: CaptureOptions::in_memory(),
+            ..Options::default()
+        };
+
+        AppState::with_options(options, Arc::new(FileSource::new(dir)))
+    }
+
+    impl CaptureSink for mpsc::Sender<CaptureMessage> {
+        fn send(&self, message: CaptureMessage) {
+            let _ = mpsc::Sender::send(self, message);
+        }
+    }
+
+    #[test]
+    fn delayed_capture_is_loaded_in_the_view() {
+        let dir = screens_dir(
+            "delayed_capture",
+            &[(4, 3, [255, 0, 0, 255]), (2, 2, [0, 0, 255, 255])],
+        );
+        let mut state = state_with_screens(&dir);
+        assert_eq!(state.get_screens().len(), 2);
+
+        state.set_timer(0.7);
+        state.set_screen_index(1);
+        assert_eq!(state.get_delay_ms(), 700 - MINIMIZE_DELAY_MS);
+
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+        state.start_screenshot(sender);
+        assert!(state.is_capturing());
+
+        let timeout = Duration::from_secs(10);
+        assert!(matches!(
+            receiver.recv_timeout(timeout),
+            Ok(CaptureMessage::Minimize)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(state.get_delay_ms()));
+
+        let result = match receiver.recv_timeout(timeout) {
+            Ok(CaptureMessage::Done(result)) => result,
+            _ => panic!("The capture has not been sent back"),
+        };
+        assert!(start.elapsed() >= Duration::from_millis(700));
+
+        let next = state.handle_screenshot_done(result);
+        assert_eq!(next, Selector::new("restore"));
+
+        let buf = state.get_buf_view();
+        assert_eq!((buf.width(), buf.height()), (2, 2));
+        assert!(buf.raw_pixels().chunks(4).all(|p| p == [0, 0, 255, 255]));
+        assert!(!state.is_capturing());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn crop_after_reset_starts_from_the_captured_origin() {
+        let dir = screens_dir("crop_after_reset", &[(8, 6, [255, 0, 0, 255])]);
+        let mut state = state_with_screens(&dir);
+        let image = state.capture_source.capture(0).unwrap();
+        state.load_capture(
+            CapturedImage {
+                image,
+                scale_factor: 1.0,
+            },
+            Some(ImageOrigin {
+                screen_index: 0,
+                left_corner: (0, 0),
+            }),
+        );
+
+        state.set_area_to_crop(Area {
+            left_corner: (2, 1),
+            width: 4,
+            height: 3,
+        });
+        state.resize_img();
+        assert_eq!(state.get_last_region().unwrap().1.left_corner, (2, 1));
+
+        let img = state.get_buf_reset();
+        state.set_buf_view(img);
+
+        state.set_area_to_crop(Area {
+            left_corner: (1, 1),
+            width: 2,
+            height: 2,
+        });
+        state.resize_img();
+        assert_eq!(state.get_last_region().unwrap().1.left_corner, (1, 1));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cancelled_capture_keeps_the_view_empty() {
+        let dir = screens_dir("cancelled_capture", &[(4, 3, [255, 0, 0, 255])]);
+        let mut state = state_with_screens(&dir);
+
+        state.set_timer(5.0);
+        let (sender, receiver) = mpsc::channel();
+        state.start_screenshot(sender);
+        state.cancel_screenshot();
+        assert!(!state.is_capturing());
+
+        // the worker ends during the delay, before minimizing the window
+        assert!(matches!(
+            receiver.recv_timeout(Duration::from_secs(2)),
+            Err(mpsc::RecvTimeoutError::Disconnected)
+        ));
+        assert!(state.get_buf_view().raw_pixels().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -218,6 +218,15 @@ pub mod druid_mod {
             Shape::Free => button_shape == data.canvas.get_shape(),
             Shape::Rubber => button_shape == data.canvas.get_shape(),
             Shape::Cut => button_shape == data.canvas.get_shape(),
+            Shape::Select => button_shape == data.canvas.get_shape(),
+            Shape::Text => button_shape == data.canvas.get_shape(),
+            Shape::Arrow => button_shape == data.canvas.get_shape(),
+            Shape::Highlighter => button_shape == data.canvas.get_shape(),
+            Shape::Redact => button_shape == data.canvas.get_shape(),
+            Shape::Ellipse => button_shape == data.canvas.get_shape(),
+            Shape::Bucket => button_shape == data.canvas.get_shape(),
+            Shape::Eyedropper => button_shape == data.canvas.get_shape(),
+            Shape::Step => button_shape == data.canvas.get_shape(),
             Shape::Fill => data.canvas.get_fill(),
             Shape::Color(val) => {
                 let shape = data.canvas.get_shape();
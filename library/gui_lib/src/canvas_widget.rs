@@ -1,20 +1,45 @@
 pub mod canvas_widget {
     use druid::{
-        kurbo::Rect,
-        piet::{Image, InterpolationMode, PietImage},
-        Affine, BoxConstraints, Env, Event, ImageBuf, LayoutCtx, PaintCtx, RenderContext, Selector,
-        Size, Widget,
+        kurbo::{Line, Rect},
+        piet::{Image, InterpolationMode, PietImage, TextLayout},
+        Affine, BoxConstraints, Color, Data, Env, Event, ImageBuf, LayoutCtx, PaintCtx, Point,
+        RenderContext, Selector, Size, Widget,
     };
     use event_lib::{canvas::canvas::*, AppState, EditState};
 
+    /// Size in pixels of the widget of the handles of the selected annotation
+    const HANDLE_SIZE: f64 = 8.0;
+    /// Distance in pixels of the widget within which an annotation is hit by the mouse
+    const HIT_TOLERANCE: f64 = 6.0;
+    const SELECTION_COLOR: Color = Color::rgb8(0x00, 0x99, 0x99);
+
+    /// Operation done by dragging the mouse with the select tool
+    #[derive(Clone, Copy)]
+    enum Drag {
+        /// Move the selected annotation, from the last point of the mouse
+        Move((i32, i32)),
+        /// Move the handle at the index of the selected annotation
+        Handle(usize),
+    }
+
     pub struct CanvasWidget {
         image_data: ImageBuf,
         start_point: (usize, usize),
         end_point: (usize, usize),
+        /// Image of the pixels, made again when they change, the annotations are painted over it
         paint_data: Option<PietImage>,
         interpolation: InterpolationMode,
         clip_area: Option<Rect>,
         widget_size: Size,
+        /// Shape being dragged, shown before it is added to the annotations
+        preview: Option<Annotation>,
+        drag: Option<Drag>,
+        /// Set once the drag changed the annotation, so that it is recorded only once in the history
+        drag_recorded: bool,
+        /// Areas painted by the redaction brush, redacted together when the mouse is released
+        brush_areas: Vec<((i32, i32), (i32, i32))>,
+        /// Last point of the image reached by the redaction brush
+        brush_point: Option<(i32, i32)>,
     }
 
     impl CanvasWidget {
@@ -28,6 +53,68 @@ pub mod canvas_widget {
                 interpolation: InterpolationMode::Bilinear,
                 clip_area: None,
                 widget_size: Size::new(0.0, 0.0),
+                preview: None,
+                drag: None,
+                drag_recorded: false,
+                brush_areas: Vec::new(),
+                brush_point: None,
+            }
+        }
+
+        /// Return the ratio between the pixels of the image and the widget
+        fn ratio(&self) -> f64 {
+            if self.widget_size.width == 0.0 {
+                return 1.0;
+            }
+
+            return self.image_data.size().width / self.widget_size.width;
+        }
+
+        fn to_image_point(&self, pos: Point) -> (i32, i32) {
+            let ratio = self.ratio();
+
+            return ((pos.x * ratio) as i32, (pos.y * ratio) as i32);
+        }
+
+        /// Start moving or resizing the annotation under the mouse, if any
+        fn start_drag(&mut self, data: &mut AppState, point: (i32, i32)) {
+            let tolerance = HIT_TOLERANCE * self.ratio();
+
+            self.drag_recorded = false;
+            self.drag = match data.canvas.handle_at(point, tolerance) {
+                Some(handle) => Some(Drag::Handle(handle)),
+                None => {
+                    let index = data.canvas.annotation_at(point, tolerance);
+                    data.canvas.select(index);
+                    index.map(|_| Drag::Move(point))
+                }
+            };
+        }
+
+        fn update_drag(&mut self, data: &mut AppState, point: (i32, i32)) {
+            let drag = match self.drag {
+                Some(drag) => drag,
+                None => return,
+            };
+
+            if !self.drag_recorded {
+                data.record_edit();
+                self.drag_recorded = true;
+            }
+
+            match drag {
+                Drag::Move(last_point) => {
+                    let (dx, dy) = (point.0 - last_point.0, point.1 - last_point.1);
+                    if let Some(annotation) = data.canvas.get_selected_annotation_mut() {
+                        annotation.translate(dx, dy);
+                    }
+                    self.drag = Some(Drag::Move(point));
+                }
+                Drag::Handle(handle) => {
+                    if let Some(annotation) = data.canvas.get_selected_annotation_mut() {
+                        annotation.move_handle(handle, point);
+                    }
+                }
             }
         }
 
@@ -51,6 +138,66 @@ pub mod canvas_widget {
             match event {
                 Event::MouseDown(mouse_event) => match data.canvas.get_shape() {
                     Shape::None => {}
+                    Shape::Select => {
+                        let point = self.to_image_point(mouse_event.pos);
+                        self.start_drag(data, point);
+                        ctx.set_active(true);
+                    }
+                    Shape::Text => {
+                        let point = self.to_image_point(mouse_event.pos);
+                        data.edit_text_at(point, HIT_TOLERANCE * self.ratio());
+                        data.remember_color();
+                        // the keys typed are sent to the focused widget
+                        ctx.request_focus();
+                        ctx.request_paint();
+                    }
+                    Shape::Bucket => {
+                        let point = self.to_image_point(mouse_event.pos);
+                        data.flood_fill_at(point);
+                        data.remember_color();
+                    }
+                    Shape::Eyedropper => {
+                        let point = self.to_image_point(mouse_event.pos);
+                        data.pick_color_at(point);
+                    }
+                    Shape::Step => {
+                        let point = self.to_image_point(mouse_event.pos);
+                        data.add_step_at(point);
+                        ctx.request_paint();
+                    }
+                    Shape::Redact if data.canvas.get_redaction_brush() => {
+                        let point = self.to_image_point(mouse_event.pos);
+                        self.brush_areas =
+                            data.canvas
+                                .brush_areas(point, point, data.get_thickness() as usize);
+                        self.brush_point = Some(point);
+                        ctx.set_active(true);
+                        ctx.request_paint();
+                    }
+                    Shape::Line
+                    | Shape::Arrow
+                    | Shape::Rectangle
+                    | Shape::Cirle
+                    | Shape::Ellipse
+                    | Shape::Redact => {
+                        self.start_point = (
+                            mouse_event.pos.x.ceil() as usize,
+                            mouse_event.pos.y.ceil() as usize,
+                        );
+                        ctx.set_active(true);
+                    }
+                    Shape::Free | Shape::Rubber | Shape::Highlighter => {
+                        // a stroke is a single edit, recorded when it starts
+                        data.record_edit();
+                        data.canvas.start_stroke();
+                        if data.canvas.get_shape() != Shape::Rubber {
+                            data.remember_color();
+                        }
+                        self.start_point = (
+                            mouse_event.pos.x.ceil() as usize,
+                            mouse_event.pos.y.ceil() as usize,
+                        );
+                    }
                     _ => {
                         self.start_point = (
                             mouse_event.pos.x.ceil() as usize,
@@ -59,10 +206,22 @@ pub mod canvas_widget {
                     }
                 },
                 Event::MouseUp(mouse_event) => match data.canvas.get_shape() {
+                    Shape::Select => {
+                        self.drag = None;
+                        ctx.set_active(false);
+                    }
+                    Shape::Text | Shape::Bucket | Shape::Eyedropper | Shape::Step => {}
+                    // the whole brush stroke is a single redaction in the history
+                    Shape::Redact if self.brush_point.is_some() => {
+                        ctx.set_active(false);
+                        self.brush_point = None;
+                        data.redact_areas(&std::mem::take(&mut self.brush_areas));
+                        ctx.request_paint();
+                    }
                     Shape::Free => {
                         data.canvas.buf_point.clear();
                     }
-                    Shape::Rubber => {
+                    Shape::Rubber | Shape::Highlighter => {
                         data.canvas.buf_point.clear();
                     }
                     Shape::Cut => {
@@ -97,46 +256,103 @@ pub mod canvas_widget {
                     }
                     Shape::None => {}
                     _ => {
+                        ctx.set_active(false);
+                        self.preview = None;
+                        ctx.request_paint();
+
                         if self.start_point == (usize::MAX, usize::MAX) {
                             return;
                         }
 
-                        self.end_point = (
-                            mouse_event.pos.x.ceil() as usize,
-                            mouse_event.pos.y.ceil() as usize,
+                        let start_point = self.to_image_point(Point::new(
+                            self.start_point.0 as f64,
+                            self.start_point.1 as f64,
+                        ));
+                        let end_point = self.to_image_point(Point::new(
+                            mouse_event.pos.x.ceil(),
+                            mouse_event.pos.y.ceil(),
+                        ));
+                        let end_point = constrain_end_point(
+                            data,
+                            start_point,
+                            end_point,
+                            mouse_event.mods.shift(),
                         );
+                        self.start_point = (usize::MAX, usize::MAX);
 
-                        let image_size = self.image_data.size();
-
-                        let ratio = image_size.width / self.widget_size.width;
+                        if start_point == end_point {
+                            return;
+                        }
 
-                        self.start_point = (
-                            ((self.start_point.0 as f64 * ratio) as usize),
-                            ((self.start_point.1 as f64 * ratio) as usize),
-                        );
-                        self.end_point = (
-                            ((self.end_point.0 as f64 * ratio) as usize),
-                            ((self.end_point.1 as f64 * ratio) as usize),
-                        );
+                        // the redaction is applied to the pixels, it is not an annotation
+                        if data.canvas.get_shape() == Shape::Redact {
+                            data.redact_area(start_point, end_point);
+                            return;
+                        }
 
-                        let buf = data.get_buf_view();
-                        let w = buf.width();
-                        let h = buf.height();
-
-                        let buf = data.canvas.draw_shape(
-                            buf.raw_pixels().to_vec(),
-                            w,
-                            h,
-                            self.start_point,
-                            self.end_point,
-                            data.canvas.get_shape(),
+                        data.record_edit();
+                        let annotation = data.canvas.new_annotation(
+                            start_point,
+                            end_point,
                             data.get_thickness() as usize,
                         );
-
-                        data.set_buf_view(buf);
+                        data.canvas.add_annotation(annotation);
+                        data.remember_color();
+                        ctx.request_paint();
                     }
                 },
                 Event::MouseMove(mouse_event) => {
+                    match data.canvas.get_shape() {
+                        Shape::Select => {
+                            if ctx.is_active() {
+                                let point = self.to_image_point(mouse_event.pos);
+                                self.update_drag(data, point);
+                            }
+                            return;
+                        }
+                        Shape::Redact if self.brush_point.is_some() => {
+                            if ctx.is_active() {
+                                let point = self.to_image_point(mouse_event.pos);
+                                let areas = data.canvas.brush_areas(
+                                    self.brush_point.unwrap_or(point),
+                                    point,
+                                    data.get_thickness() as usize,
+                                );
+                                self.brush_areas.extend(areas);
+                                self.brush_point = Some(point);
+                                ctx.request_paint();
+                            }
+                            return;
+                        }
+                        Shape::Line
+                        | Shape::Arrow
+                        | Shape::Rectangle
+                        | Shape::Cirle
+                        | Shape::Ellipse
+                        | Shape::Redact => {
+                            if ctx.is_active() && self.start_point != (usize::MAX, usize::MAX) {
+                                let start_point = self.to_image_point(Point::new(
+                                    self.start_point.0 as f64,
+                                    self.start_point.1 as f64,
+                                ));
+                                let end_point = constrain_end_point(
+                                    data,
+                                    start_point,
+                                    self.to_image_point(mouse_event.pos),
+                                    mouse_event.mods.shift(),
+                                );
+                                self.preview = Some(data.canvas.new_annotation(
+                                    start_point,
+                                    end_point,
+                                    data.get_thickness() as usize,
+                                ));
+                                ctx.request_paint();
+                            }
+                            return;
+                        }
+                        _ => {}
+                    }
+
                     if !mouse_event.buttons.has_left() {
                         data.canvas.buf_point.clear();
                         return;
@@ -162,7 +378,8 @@ pub mod canvas_widget {
                             + (data.get_thickness() / 2.0) as usize,
                     );
 
-                    if shape == Shape::Free || shape == Shape::Rubber {
+                    if shape == Shape::Free || shape == Shape::Rubber || shape == Shape::Highlighter
+                    {
                         if data.canvas.buf_point.len() <= 1 {
                             data.canvas.buf_point.push_back(current_point);
                         }
@@ -178,13 +395,24 @@ pub mod canvas_widget {
 
                             match shape {
                                 Shape::Free => {
-                                    let new_buf = data.canvas.draw_shape(
+                                    let new_buf = data.canvas.draw_stroke(
+                                        buf.raw_pixels().to_vec(),
+                                        w,
+                                        h,
+                                        p1,
+                                        p2,
+                                        data.get_thickness() as usize,
+                                    );
+
+                                    data.set_buf_view(new_buf);
+                                }
+                                Shape::Highlighter => {
+                                    let new_buf = data.canvas.draw_highlight(
                                         buf.raw_pixels().to_vec(),
                                         w,
                                         h,
                                         p1,
                                         p2,
-                                        Shape::Line,
                                         data.get_thickness() as usize,
                                     );
 
@@ -217,6 +445,27 @@ pub mod canvas_widget {
                         }
                     }
                 }
+                Event::KeyDown(key_event) => {
+                    if data.canvas.get_editing().is_none()
+                        || key_event.mods.ctrl()
+                        || key_event.mods.meta()
+                    {
+                        return;
+                    }
+
+                    match &key_event.key {
+                        druid::keyboard_types::Key::Character(text) => {
+                            data.canvas.insert_text(text)
+                        }
+                        druid::keyboard_types::Key::Enter => data.canvas.insert_text("\n"),
+                        druid::keyboard_types::Key::Backspace => data.canvas.delete_last_char(),
+                        druid::keyboard_types::Key::Escape => data.canvas.finish_editing(),
+                        _ => return,
+                    }
+
+                    ctx.set_handled();
+                    ctx.request_paint();
+                }
                 Event::Command(ref c) => {
                     if c.is(Selector::<()>::new("resize")) {
                         ctx.request_layout();
@@ -238,11 +487,17 @@ pub mod canvas_widget {
 
         fn update(
             &mut self,
-            _ctx: &mut druid::UpdateCtx,
-            _old_data: &AppState,
-            _data: &AppState,
+            ctx: &mut druid::UpdateCtx,
+            old_data: &AppState,
+            data: &AppState,
             _env: &druid::Env,
         ) {
+            if !old_data.get_buf_view().same(&data.get_buf_view()) {
+                self.paint_data = None;
+                ctx.request_paint();
+            } else if !old_data.canvas.same(&data.canvas) {
+                ctx.request_paint();
+            }
         }
 
         fn layout(
@@ -274,7 +529,7 @@ pub mod canvas_widget {
             return size;
         }
 
-        fn paint(&mut self, ctx: &mut PaintCtx, _data: &AppState, _env: &Env) {
+        fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, _env: &Env) {
             let image_size = self.image_size();
             let parent = ctx.size();
             let fit_box = image_size;
@@ -291,29 +546,117 @@ pub mod canvas_widget {
             let clip_rect = ctx.size().to_rect();
             ctx.clip(clip_rect);
 
-            let piet_image = {
-                let image_data = &self.image_data;
-                self.paint_data
-                    .get_or_insert_with(|| image_data.to_image(ctx.render_ctx))
-            };
+            // the shapes are painted over the image as they are saved,
+            // only the area of a redaction is shown until it is applied
+            let shape_preview = self
+                .preview
+                .as_ref()
+                .filter(|annotation| annotation.shape != Shape::Redact);
+            let redact_preview = self
+                .preview
+                .as_ref()
+                .filter(|annotation| annotation.shape == Shape::Redact);
+
+            let piet_image = self
+                .paint_data
+                .get_or_insert_with(|| data.get_buf_view().to_image(ctx.render_ctx));
             if piet_image.size().is_empty() {
                 // zero-sized image = nothing to draw
                 return;
             }
             ctx.with_save(|ctx| {
                 // we have to re-do this because the whole struct is moved into the closure.
-                let piet_image = {
-                    let image_data = &self.image_data;
-                    self.paint_data
-                        .get_or_insert_with(|| image_data.to_image(ctx.render_ctx))
-                };
+                let piet_image = self
+                    .paint_data
+                    .get_or_insert_with(|| data.get_buf_view().to_image(ctx.render_ctx));
                 ctx.transform(offset_matrix);
                 if let Some(area) = self.clip_area {
                     ctx.draw_image_area(piet_image, area, image_size.to_rect(), self.interpolation);
                 } else {
                     ctx.draw_image(piet_image, image_size.to_rect(), self.interpolation);
                 }
+
+                for annotation in data.canvas.annotations.iter().chain(shape_preview) {
+                    paint_annotation(ctx.render_ctx, annotation);
+                }
+
+                if let Some(annotation) = redact_preview {
+                    let (x0, y0, x1, y1) = annotation.bounds();
+                    let rect = Rect::new(x0 as f64, y0 as f64, x1 as f64, y1 as f64);
+                    ctx.fill(rect, &Color::rgba8(0, 0, 0, 100));
+                    ctx.stroke(rect, &SELECTION_COLOR, 2.0);
+                }
+
+                for ((x0, y0), (x1, y1)) in &self.brush_areas {
+                    let rect = Rect::new(*x0 as f64, *y0 as f64, *x1 as f64, *y1 as f64);
+                    ctx.fill(rect, &Color::rgba8(0, 0, 0, 100));
+                }
+
+                if let Some(index) = data.canvas.get_editing() {
+                    paint_text_cursor(ctx, &data.canvas.annotations[index]);
+                }
             });
+
+            // the handles keep the same size whatever the zoom of the image
+            if let Some(annotation) = data.canvas.get_selected_annotation() {
+                for handle in annotation.handles() {
+                    let center = offset_matrix * Point::new(handle.0 as f64, handle.1 as f64);
+                    let rect = Rect::from_center_size(center, (HANDLE_SIZE, HANDLE_SIZE));
+                    ctx.fill(rect, &Color::WHITE);
+                    ctx.stroke(rect, &SELECTION_COLOR, 1.5);
+                }
+            }
         }
     }
+
+    /// Return the end of the dragged rect, with Shift the rect of an ellipse is a square
+    /// so that it is drawn as a circle
+    fn constrain_end_point(
+        data: &AppState,
+        start: (i32, i32),
+        end: (i32, i32),
+        constrained: bool,
+    ) -> (i32, i32) {
+        if !constrained || data.canvas.get_shape() != Shape::Ellipse {
+            return end;
+        }
+
+        let side = (end.0 - start.0).abs().max((end.1 - start.1).abs());
+        let direction = (
+            if end.0 < start.0 { -1 } else { 1 },
+            if end.1 < start.1 { -1 } else { 1 },
+        );
+
+        return (start.0 + side * direction.0, start.1 + side * direction.1);
+    }
+
+    /// Draw the box of the text being edited and the cursor after its last character
+    fn paint_text_cursor(ctx: &mut PaintCtx, annotation: &Annotation) {
+        let (x0, y0, x1, y1) = annotation.bounds();
+        let padding = annotation.text_padding();
+        let line_width = (annotation.font_size / 16.0).max(1.0);
+
+        ctx.stroke(
+            Rect::new(x0 as f64, y0 as f64, x1 as f64, y1 as f64),
+            &SELECTION_COLOR,
+            line_width,
+        );
+
+        let position = match build_text_layout(ctx.render_ctx, annotation) {
+            Some(layout) => layout.hit_test_text_position(annotation.text.len()).point,
+            // the baseline of an empty text
+            None => Point::new(0.0, annotation.font_size * 0.8),
+        };
+        let x = x0 as f64 + padding + position.x;
+        let y = y0 as f64 + padding + position.y;
+
+        ctx.stroke(
+            Line::new(
+                (x, y - annotation.font_size * 0.8),
+                (x, y + annotation.font_size * 0.2),
+            ),
+            &Color::from_rgba32_u32(annotation.color),
+            line_width,
+        );
+    }
 }
@@ -1,18 +1,19 @@
 mod button_mod;
 mod flex_mod;
 pub mod canvas_widget;
+pub mod region_overlay;
 use button_mod::druid_mod::*;
 use canvas_widget::canvas_widget::CanvasWidget;
+use region_overlay::region_overlay::RegionOverlay;
 use druid::{
     widget::{*, self}, Color, Env, ImageBuf, KeyOrValue, LocalizedString, Menu, MenuItem, Widget,
-    WidgetExt, WindowId, Command, Selector, Target, Event,
+    WidgetExt, WindowId, Command, Selector, Target, Event, WindowDesc, LensExt, RenderContext,
 };
-use event_lib::{*, canvas::canvas::Canvas};
+use event_lib::{*, canvas::canvas::{ArrowHeads, Canvas, Connectivity, RedactionMode}, color::color::ColorPicker};
 use flex_mod::druid_mod::*;
 use shortcut_lib::*;
-use screenshot_lib::number_of_screens;
+use screenshot_lib::{recording::recording::RecordingFormat, ALL_SCREENS};
 use core::panic;
-use std::time::Duration;
 use strum::IntoEnumIterator;
 use native_dialog::MessageDialog;
 
@@ -45,6 +46,75 @@ pub fn build_menu(_window: Option<WindowId>, _data: &AppState) -> Menu<event_lib
                         }
                     }),
             )
+            .entry(
+                MenuItem::new("Capture last region")
+                    .on_activate(move |ctx, _data: &mut AppState, _| {
+                        ctx.submit_command(Command::new(Selector::new("capture_last_region"), (), Target::Auto));
+                    })
+                    .dynamic_hotkey(|data: &AppState, _env: &Env| {
+                        data.get_shortcuts()
+                            .extract_value_for_menu(Action::CaptureLastRegion)
+                    }).enabled_if(|data: &AppState, _| {
+                        if (data.get_edit_state() == EditState::None) || (data.get_edit_state() == EditState::Drawing) {
+                            if data.get_view_state() == ViewState::MainView {
+                                data.has_last_region()
+                            }else{
+                                false
+                            }
+                        }else{
+                            false
+                        }
+                    }),
+            )
+            .entry(
+                MenuItem::new("Cancel screenshot")
+                    .on_activate(move |ctx, _data: &mut AppState, _| {
+                        ctx.submit_command(Command::new(Selector::new("cancel_screenshot"), (), Target::Auto));
+                    })
+                    .dynamic_hotkey(|data: &AppState, _env: &Env| {
+                        data.get_shortcuts()
+                            .extract_value_for_menu(Action::CancelScreenshot)
+                    }).enabled_if(|data: &AppState, _| data.is_capture_running()),
+            )
+            .separator()
+            .entry(
+                MenuItem::new("Open image...")
+                    .on_activate(|_ctx, data: &mut AppState, _| {
+                        data.open_image();
+                    })
+                    .dynamic_hotkey(|data: &AppState, _env: &Env| {
+                        data.get_shortcuts().extract_value_for_menu(Action::OpenImage)
+                    }).enabled_if(|data: &AppState, _| {
+                        if (data.get_edit_state() == EditState::None) || (data.get_edit_state() == EditState::Drawing) {
+                            if data.get_view_state() == ViewState::MainView {
+                                true
+                            }else{
+                                false
+                            }
+                        }else{
+                            false
+                        }
+                    }),
+            )
+            .entry(
+                MenuItem::new("Paste from clipboard")
+                    .on_activate(|_ctx, data: &mut AppState, _| {
+                        data.paste_image();
+                    })
+                    .dynamic_hotkey(|data: &AppState, _env: &Env| {
+                        data.get_shortcuts().extract_value_for_menu(Action::PasteImage)
+                    }).enabled_if(|data: &AppState, _| {
+                        if (data.get_edit_state() == EditState::None) || (data.get_edit_state() == EditState::Drawing) {
+                            if data.get_view_state() == ViewState::MainView {
+                                true
+                            }else{
+                                false
+                            }
+                        }else{
+                            false
+                        }
+                    }),
+            )
             .separator()
             .entry(
                 MenuItem::new("Save")
@@ -86,6 +156,40 @@ pub fn build_menu(_window: Option<WindowId>, _data: &AppState) -> Menu<event_lib
             ),
     );
 
+    base = base.entry(
+        Menu::new(LocalizedString::new("common-menu-edit-menu"))
+            .entry(
+                MenuItem::new("Undo")
+                    .on_activate(|_ctx, data: &mut AppState, _| {
+                        data.undo();
+                    })
+                    .dynamic_hotkey(|data: &AppState, _env: &Env| {
+                        data.get_shortcuts().extract_value_for_menu(Action::Undo)
+                    }).enabled_if(|data: &AppState, _| {
+                        if (data.get_edit_state() == EditState::None) || (data.get_edit_state() == EditState::Drawing) {
+                            data.get_view_state() == ViewState::MainView && data.can_undo()
+                        }else{
+                            false
+                        }
+                    }),
+            )
+            .entry(
+                MenuItem::new("Redo")
+                    .on_activate(|_ctx, data: &mut AppState, _| {
+                        data.redo();
+                    })
+                    .dynamic_hotkey(|data: &AppState, _env: &Env| {
+                        data.get_shortcuts().extract_value_for_menu(Action::Redo)
+                    }).enabled_if(|data: &AppState, _| {
+                        if (data.get_edit_state() == EditState::None) || (data.get_edit_state() == EditState::Drawing) {
+                            data.get_view_state() == ViewState::MainView && data.can_redo()
+                        }else{
+                            false
+                        }
+                    }),
+            ),
+    );
+
     return base;
 }
 
@@ -131,6 +235,67 @@ impl View {
                         },
                     );
 
+                    let button_new_screenshot_window = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/window.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::None,
+                        |_, data: &mut AppState, _| {
+                            match data.load_windows() {
+                                Ok(_) => data.set_edit_state(EditState::WindowPicking),
+                                Err(err) => {
+                                    MessageDialog::new()
+                                        .set_title("Unable to list windows")
+                                        .set_text(&err.to_string())
+                                        .set_type(native_dialog::MessageType::Error)
+                                        .show_alert()
+                                        .unwrap();
+                                }
+                            }
+                        },
+                    );
+
+                    let button_region = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/region.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::None,
+                        |ctx, data: &mut AppState, _| {
+                            data.set_edit_state(EditState::None);
+                            data.start_region_screenshot(ctx.get_external_handle());
+                        },
+                    );
+
+                    let button_open = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/open.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::None,
+                        |_, data: &mut AppState, _| data.open_image(),
+                    );
+
+                    let button_interval = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/interval.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::None,
+                        |ctx, data: &mut AppState, _| {
+                            data.set_edit_state(EditState::None);
+                            data.start_interval_capture(ctx.get_external_handle());
+                        },
+                    );
+
+                    let button_record = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/record.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::None,
+                        |ctx, data: &mut AppState, _| {
+                            data.set_edit_state(EditState::None);
+                            data.start_recording(ctx.get_external_handle());
+                        },
+                    );
+
                     let button_drawing = FlexMod::row(false).with_flex_child(TransparentButton::with_bg(
                         Image::new(
                         ImageBuf::from_file(format!("{}/edit.png", UI_IMG_PATH)).unwrap(),
@@ -208,6 +373,11 @@ impl View {
                     let left_part = Flex::row()
                         .main_axis_alignment(druid::widget::MainAxisAlignment::Start)
                         .with_flex_child(button_new_screenshot_full, 1.0)
+                        .with_flex_child(button_new_screenshot_window, 1.0)
+                        .with_flex_child(button_region, 1.0)
+                        .with_flex_child(button_interval, 1.0)
+                        .with_flex_child(button_record, 1.0)
+                        .with_flex_child(button_open, 1.0)
                         .must_fill_main_axis(false);
 
                     let right_part = Flex::row()
@@ -234,7 +404,7 @@ impl View {
                         ),
                         canvas::canvas::Shape::Color(0xff0000ff),
                         |ctx, data: &mut AppState, _| {
-                            data.canvas.set_color(0xff0000ff);
+                            data.set_color(0xff0000ff);
                             ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
                         }
                     ).disabled_if(|data, _|{
@@ -251,7 +421,7 @@ impl View {
                         ),
                         canvas::canvas::Shape::Color(0x00ff00ff),
                         |ctx, data: &mut AppState, _| {
-                            data.canvas.set_color(0x00ff00ff);
+                            data.set_color(0x00ff00ff);
                             ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
                         }
                     ).disabled_if(|data, _|{
@@ -268,7 +438,7 @@ impl View {
                         ),
                         canvas::canvas::Shape::Color(0x0000ffff),
                         |ctx, data: &mut AppState, _| {
-                            data.canvas.set_color(0x0000ffff);
+                            data.set_color(0x0000ffff);
                             ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
                         }
                     ).disabled_if(|data, _|{
@@ -280,6 +450,17 @@ impl View {
                         }
                     });
 
+                    let button_eyedropper = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/eyedropper.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::Eyedropper,
+                        |ctx, data: &mut AppState, _| {
+                            data.canvas.set_shape(canvas::canvas::Shape::Eyedropper);
+                            ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
+                        },
+                    );
+
                     let button_no_drawing = TransparentButton::with_bg(
                         Image::new(
                             ImageBuf::from_file(format!("{}/no_edit.png", UI_IMG_PATH)).unwrap(),
@@ -348,6 +529,113 @@ impl View {
                             ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
                         },
                     );
+                    let button_ellipse = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/ellipse.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::Ellipse,
+                        |ctx, data: &mut AppState, _| {
+                            data.canvas.set_shape(canvas::canvas::Shape::Ellipse);
+                            ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
+                        },
+                    );
+                    let button_highlighter = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/highlighter.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::Highlighter,
+                        |ctx, data: &mut AppState, _| {
+                            data.canvas.set_shape(canvas::canvas::Shape::Highlighter);
+                            ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
+                        },
+                    );
+                    let button_arrow = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/arrow.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::Arrow,
+                        |ctx, data: &mut AppState, _| {
+                            data.canvas.set_shape(canvas::canvas::Shape::Arrow);
+                            ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
+                        },
+                    );
+                    let button_text = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/text.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::Text,
+                        |ctx, data: &mut AppState, _| {
+                            data.canvas.set_shape(canvas::canvas::Shape::Text);
+                            ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
+                        },
+                    );
+                    let button_select = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/select.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::Select,
+                        |ctx, data: &mut AppState, _| {
+                            data.canvas.set_shape(canvas::canvas::Shape::Select);
+                            ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
+                        },
+                    );
+                    let button_delete = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/delete.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::None,
+                        |_ctx, data: &mut AppState, _| {
+                            data.delete_selected_annotation();
+                        },
+                    ).disabled_if(|data, _|{return data.canvas.get_selected().is_none();});
+                    let button_front = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/to_front.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::None,
+                        |_ctx, data: &mut AppState, _| {
+                            data.bring_selected_to_front();
+                        },
+                    ).disabled_if(|data, _|{return data.canvas.get_selected().is_none();});
+                    let button_back = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/to_back.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::None,
+                        |_ctx, data: &mut AppState, _| {
+                            data.send_selected_to_back();
+                        },
+                    ).disabled_if(|data, _|{return data.canvas.get_selected().is_none();});
+                    let button_redact = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/redact.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::Redact,
+                        |ctx, data: &mut AppState, _| {
+                            data.canvas.set_shape(canvas::canvas::Shape::Redact);
+                            ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
+                        },
+                    );
+                    let button_bucket = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/bucket.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::Bucket,
+                        |ctx, data: &mut AppState, _| {
+                            data.canvas.set_shape(canvas::canvas::Shape::Bucket);
+                            ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
+                        },
+                    );
+                    let button_step = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/step.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::Step,
+                        |ctx, data: &mut AppState, _| {
+                            data.canvas.set_shape(canvas::canvas::Shape::Step);
+                            ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
+                        },
+                    );
                     let button_scissors = TransparentButton::with_bg(
                         Image::new(
                             ImageBuf::from_file(format!("{}/scissors.png", UI_IMG_PATH)).unwrap(),
@@ -371,11 +659,12 @@ impl View {
                         canvas::canvas::Shape::None,
                         |_ctx, data: &mut AppState, _| {
                             match MessageDialog::new().set_title("Do you want to restore the initial image?")
-                                                            .set_text("If you confirm all changes made will be deleted")
+                                                            .set_text("If you confirm all changes made will be deleted, the redacted areas stay hidden")
                                                             .show_confirm() {
                                     Ok(confirm) => {
                                         if confirm {
-                                            data.canvas= Canvas::new();
+                                            data.record_edit();
+                                            data.canvas.reset_edits();
                                             let img = data.get_buf_reset();
                                             data.set_buf_view(img);
                                         }
@@ -385,13 +674,39 @@ impl View {
                         },
                     ).disabled_if(|data, _|{return !data.is_modified();});
 
+                    let button_undo = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/undo.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::None,
+                        |_ctx, data: &mut AppState, _| {
+                            data.undo();
+                        },
+                    ).disabled_if(|data, _|{return !data.can_undo();});
+                    let button_redo = TransparentButton::with_bg(
+                        Image::new(
+                            ImageBuf::from_file(format!("{}/redo.png", UI_IMG_PATH)).unwrap(),
+                        ),
+                        canvas::canvas::Shape::None,
+                        |_ctx, data: &mut AppState, _| {
+                            data.redo();
+                        },
+                    ).disabled_if(|data, _|{return !data.can_redo();});
+
                     FlexMod::row(false)
-                    .with_child(Flex::row().with_child(button_red_color).with_child(button_green_color).with_child(button_blue_color).padding((20.0,0.0)))
+                    .with_child(Flex::row().with_child(button_red_color).with_child(button_green_color).with_child(button_blue_color).with_child(button_eyedropper).padding((20.0,0.0)))
+                    .with_child(View::build_color_picker().padding((20.0,0.0)))
                     .with_child(Flex::row().with_child(View::build_thickness_slider()).padding((20.0,0.0)))
-                    .with_child(Flex::row().with_child(button_free).with_child(button_line).with_child(button_rectangle).with_child(button_circle).with_child(button_rubber).padding((20.0,0.0)))
+                    .with_child(Flex::row().with_child(button_free).with_child(button_highlighter).with_child(button_line).with_child(button_arrow).with_child(button_rectangle).with_child(button_circle).with_child(button_ellipse).with_child(button_text).with_child(button_rubber).padding((20.0,0.0)))
+                    .with_child(Flex::row().with_child(button_step).with_child(View::build_step_options()).padding((20.0,0.0)))
+                    .with_child(View::build_text_options().padding((20.0,0.0)))
+                    .with_child(View::build_arrow_options().padding((20.0,0.0)))
                     .with_child(Flex::row().with_child(button_fill).padding((20.0,0.0)))
+                    .with_child(Flex::row().with_child(button_bucket).with_child(View::build_bucket_options()).padding((20.0,0.0)))
+                    .with_child(Flex::row().with_child(button_select).with_child(button_delete).with_child(button_front).with_child(button_back).padding((20.0,0.0)))
+                    .with_child(Flex::row().with_child(button_redact).with_child(View::build_redaction_options()).padding((20.0,0.0)))
                     .with_child(Flex::row().with_child(button_scissors).padding((20.0,0.0)))
-                    .with_child(Flex::row().with_child(button_reset).padding((20.0,0.0)))
+                    .with_child(Flex::row().with_child(button_undo).with_child(button_redo).with_child(button_reset).padding((20.0,0.0)))
                     .with_child(Flex::row().with_child(button_no_drawing).padding((20.0,0.0)))
                     .visible_if(|data: &AppState| data.get_edit_state() == EditState::Drawing).center()
                 };
@@ -428,7 +743,32 @@ impl View {
                         .center()
                 };
 
+                let capturing_top_bar = {
+                    let mut label = Label::new(|data: &AppState, _: &_| {
+                        if data.is_recording_running() {
+                            format!("Recording: {} frames captured", data.get_recording_frames())
+                        } else if data.is_interval_running() {
+                            match data.get_interval_settings().count {
+                                Some(count) => format!("Interval capture: {}/{} frames saved", data.get_interval_frames(), count),
+                                Option::None => format!("Interval capture: {} frames saved", data.get_interval_frames()),
+                            }
+                        } else {
+                            format!("Screenshot in progress...")
+                        }
+                    });
+                    label.set_text_color(Color::WHITE);
+
+                    FlexMod::row(false)
+                        .with_child(label.padding((0.0, 15.0)))
+                        .with_child(Button::new(|data: &AppState, _: &_| if data.is_interval_running() || data.is_recording_running() { format!("Stop") } else { format!("Cancel") })
+                            .on_click(|_, data: &mut AppState, _| data.stop_capture())
+                            .padding((20.0, 0.0)))
+                        .visible_if(|data: &AppState| data.is_capture_running())
+                        .center()
+                };
+
                 FlexMod::column(true)
+                    .with_child(capturing_top_bar)
                     .with_child(normal_top_bar)
                     .with_child(resize_top_bar)
                     .with_child(drawing_top_bar)
@@ -475,6 +815,289 @@ impl View {
         return Flex::column().with_child(label_1).with_child(Flex::row().with_child(thickness_slider).with_child(label_2));
     }
 
+    fn build_color_picker() -> impl Widget<AppState> {
+        let swatch = Painter::new(|ctx, data: &AppState, _| {
+            let rect = ctx.size().to_rect();
+            ctx.fill(rect, &Color::from_rgba32_u32(data.canvas.get_color()));
+            ctx.stroke(rect, &Color::WHITE, 1.0);
+        })
+        .fix_size(24.0, 24.0)
+        .on_click(|_, data: &mut AppState, _| data.update_color_picker(|picker| picker.open = !picker.open));
+
+        let mut recent_colors = Flex::row();
+        for i in 0..RECENT_COLORS_LIMIT {
+            recent_colors.add_child(
+                Painter::new(move |ctx, data: &AppState, _| {
+                    if let Some(color) = data.get_recent_colors().get(i) {
+                        let rect = ctx.size().to_rect();
+                        ctx.fill(rect, &Color::from_rgba32_u32(*color));
+                    }
+                })
+                .fix_size(14.0, 14.0)
+                .padding(1.0)
+                .on_click(move |ctx, data: &mut AppState, _| {
+                    if let Some(color) = data.get_recent_colors().get(i) {
+                        data.set_color(*color);
+                        ctx.submit_command(Command::new(Selector::new("repaint"), (), Target::Auto));
+                    }
+                }),
+            );
+        }
+
+        let mut label = Label::new("Recent");
+        label.set_text_size(10.0);
+        label.set_text_color(Color::WHITE);
+
+        let color_text = TextBox::new()
+            .with_placeholder("#RRGGBBAA or R,G,B,A")
+            .fix_width(150.0)
+            .lens(AppState::color_picker.then(ColorPicker::text));
+        let button_set = Button::new("Set").on_click(|_, data: &mut AppState, _| data.apply_color_text());
+
+        let mut rgba_label = Label::dynamic(|data: &AppState, _| {
+            let [r, g, b, a] = data.canvas.get_color().to_be_bytes();
+            format!("RGBA {} {} {} {}", r, g, b, a)
+        });
+        rgba_label.set_text_size(10.0);
+        rgba_label.set_text_color(Color::WHITE);
+
+        let picker = FlexMod::row(false)
+            .with_child(Flex::column()
+                .with_child(View::build_picker_slider("Hue", 360.0, |picker| picker.hue, |picker, hue| picker.hue = hue))
+                .with_child(View::build_picker_slider("Saturation", 1.0, |picker| picker.saturation, |picker, saturation| picker.saturation = saturation)))
+            .with_child(Flex::column()
+                .with_child(View::build_picker_slider("Value", 1.0, |picker| picker.value, |picker, value| picker.value = value))
+                .with_child(View::build_picker_slider("Opacity", 1.0, |picker| picker.opacity, |picker, opacity| picker.opacity = opacity)))
+            .with_child(Flex::column()
+                .with_child(Flex::row().with_child(color_text).with_child(button_set))
+                .with_child(rgba_label)
+                .padding((10.0, 0.0)))
+            .visible_if(|data: &AppState| data.get_color_picker().open);
+
+        return Flex::row()
+            .with_child(swatch)
+            .with_child(Flex::column().with_child(label).with_child(recent_colors).padding((10.0, 0.0)))
+            .with_child(picker);
+    }
+
+    /// Slider of a component of the colour picker, from 0 to `max`
+    fn build_picker_slider(
+        name: &str,
+        max: f64,
+        get: fn(&ColorPicker) -> f64,
+        set: fn(&mut ColorPicker, f64),
+    ) -> impl Widget<AppState> {
+        let slider =
+            Slider::new()
+                .with_range(0.0, max)
+                .track_color(KeyOrValue::Concrete(Color::TEAL))
+                .knob_style(KnobStyle::Wedge)
+                .axis(druid::widget::Axis::Horizontal)
+                .fix_width(120.0)
+                .lens(druid::lens::Map::new(
+                    move |data: &AppState| get(&data.get_color_picker()),
+                    move |data: &mut AppState, value: f64| data.update_color_picker(|picker| set(picker, value)),
+                ));
+
+        let mut label = Label::new(name);
+        label.set_text_size(10.0);
+        label.set_text_color(Color::WHITE);
+
+        return Flex::column().with_child(label).with_child(slider).padding((10.0, 0.0));
+    }
+
+    fn build_text_options() -> impl Widget<AppState> {
+        let font_size_slider =
+            Slider::new()
+                .with_range(8.0, 96.0)
+                .track_color(KeyOrValue::Concrete(Color::TEAL))
+                .knob_style(KnobStyle::Wedge)
+                .axis(druid::widget::Axis::Horizontal)
+                .with_step(2.0)
+                .fix_width(120.0)
+                .lens(AppState::canvas.then(druid::lens::Map::new(
+                    |canvas: &Canvas| canvas.get_font_size(),
+                    |canvas: &mut Canvas, font_size: f64| canvas.set_font_size(font_size),
+                )));
+
+        let mut label_1 = Label::new("Font size");
+        label_1.set_text_size(10.0);
+        label_1.set_text_color(Color::WHITE);
+
+        let mut label_2 = Label::new(|data: &AppState, _: &_| format!("{} px", data.canvas.get_font_size()));
+        label_2.set_text_size(12.0);
+        label_2.set_text_color(Color::WHITE);
+
+        let background_checkbox = Checkbox::new("Box")
+            .lens(AppState::canvas.then(druid::lens::Map::new(
+                |canvas: &Canvas| canvas.get_text_background(),
+                |canvas: &mut Canvas, background: bool| canvas.set_text_background(background),
+            )));
+
+        return FlexMod::row(false)
+            .with_child(Flex::column().with_child(label_1).with_child(Flex::row().with_child(font_size_slider).with_child(label_2)))
+            .with_child(background_checkbox.padding((10.0, 0.0)))
+            .visible_if(|data: &AppState| data.canvas.get_shape() == canvas::canvas::Shape::Text);
+    }
+
+    fn build_redaction_options() -> impl Widget<AppState> {
+        let mode_radio = RadioGroup::row(vec![
+            ("Pixelate", RedactionMode::Pixelate),
+            ("Blur", RedactionMode::Blur),
+            ("Solid", RedactionMode::Solid),
+        ])
+        .lens(AppState::canvas.then(druid::lens::Map::new(
+            |canvas: &Canvas| canvas.get_redaction_mode(),
+            |canvas: &mut Canvas, mode: RedactionMode| canvas.set_redaction_mode(mode),
+        )));
+
+        let strength_slider =
+            Slider::new()
+                .with_range(2.0, 30.0)
+                .track_color(KeyOrValue::Concrete(Color::TEAL))
+                .knob_style(KnobStyle::Wedge)
+                .axis(druid::widget::Axis::Horizontal)
+                .with_step(1.0)
+                .fix_width(120.0)
+                .lens(AppState::canvas.then(druid::lens::Map::new(
+                    |canvas: &Canvas| canvas.get_redaction_strength(),
+                    |canvas: &mut Canvas, strength: f64| canvas.set_redaction_strength(strength),
+                )))
+                .disabled_if(|data: &AppState, _| data.canvas.get_redaction_mode() == RedactionMode::Solid);
+
+        let mut label = Label::new("Strength");
+        label.set_text_size(10.0);
+        label.set_text_color(Color::WHITE);
+
+        let brush_checkbox = Checkbox::new("Brush")
+            .lens(AppState::canvas.then(druid::lens::Map::new(
+                |canvas: &Canvas| canvas.get_redaction_brush(),
+                |canvas: &mut Canvas, brush: bool| canvas.set_redaction_brush(brush),
+            )));
+
+        return FlexMod::row(false)
+            .with_child(mode_radio)
+            .with_child(Flex::column().with_child(label).with_child(strength_slider).padding((10.0, 0.0)))
+            .with_child(brush_checkbox)
+            .visible_if(|data: &AppState| data.canvas.get_shape() == canvas::canvas::Shape::Redact);
+    }
+
+    fn build_bucket_options() -> impl Widget<AppState> {
+        let connectivity_radio = RadioGroup::row(vec![
+            ("4-way", Connectivity::Four),
+            ("8-way", Connectivity::Eight),
+        ])
+        .lens(AppState::canvas.then(druid::lens::Map::new(
+            |canvas: &Canvas| canvas.get_connectivity(),
+            |canvas: &mut Canvas, connectivity: Connectivity| canvas.set_connectivity(connectivity),
+        )));
+
+        let tolerance_slider =
+            Slider::new()
+                .with_range(0.0, 255.0)
+                .track_color(KeyOrValue::Concrete(Color::TEAL))
+                .knob_style(KnobStyle::Wedge)
+                .axis(druid::widget::Axis::Horizontal)
+                .with_step(1.0)
+                .fix_width(120.0)
+                .lens(AppState::canvas.then(druid::lens::Map::new(
+                    |canvas: &Canvas| canvas.get_fill_tolerance(),
+                    |canvas: &mut Canvas, tolerance: f64| canvas.set_fill_tolerance(tolerance),
+                )));
+
+        let mut label = Label::new("Tolerance");
+        label.set_text_size(10.0);
+        label.set_text_color(Color::WHITE);
+
+        return FlexMod::row(false)
+            .with_child(connectivity_radio)
+            .with_child(Flex::column().with_child(label).with_child(tolerance_slider).padding((10.0, 0.0)))
+            .visible_if(|data: &AppState| data.canvas.get_shape() == canvas::canvas::Shape::Bucket);
+    }
+
+    fn build_step_options() -> impl Widget<AppState> {
+        let mut label = Label::new("Next");
+        label.set_text_size(10.0);
+        label.set_text_color(Color::WHITE);
+
+        let mut number_label = Label::dynamic(|data: &AppState, _| data.canvas.get_next_step().to_string());
+        number_label.set_text_color(Color::WHITE);
+
+        let number_stepper = Stepper::new()
+            .with_range(1.0, 999.0)
+            .with_step(1.0)
+            .lens(AppState::canvas.then(druid::lens::Map::new(
+                |canvas: &Canvas| canvas.get_next_step() as f64,
+                |canvas: &mut Canvas, number: f64| canvas.set_next_step(number as u32),
+            )));
+
+        let button_reset = Button::new("Reset").on_click(|_, data: &mut AppState, _| data.canvas.reset_steps());
+
+        return FlexMod::row(false)
+            .with_child(Flex::column().with_child(label).with_child(Flex::row().with_child(number_label).with_child(number_stepper)).padding((10.0, 0.0)))
+            .with_child(button_reset)
+            .visible_if(|data: &AppState| data.canvas.get_shape() == canvas::canvas::Shape::Step);
+    }
+
+    fn build_arrow_options() -> impl Widget<AppState> {
+        let mut label = Label::new("Heads");
+        label.set_text_size(10.0);
+        label.set_text_color(Color::WHITE);
+
+        let heads_radio = RadioGroup::row(vec![
+            ("None", ArrowHeads::None),
+            ("Single", ArrowHeads::End),
+            ("Double", ArrowHeads::Both),
+        ])
+        .lens(AppState::canvas.then(druid::lens::Map::new(
+            |canvas: &Canvas| canvas.get_arrow_heads(),
+            |canvas: &mut Canvas, heads: ArrowHeads| canvas.set_arrow_heads(heads),
+        )));
+
+        return FlexMod::row(false)
+            .with_child(Flex::column().with_child(label).with_child(heads_radio))
+            .visible_if(|data: &AppState| data.canvas.get_shape() == canvas::canvas::Shape::Arrow);
+    }
+
+    fn build_window_picker() -> impl Widget<AppState> {
+        let windows_list = ViewSwitcher::new(
+            |data: &AppState, _| data.get_windows(),
+            |windows, _, _| {
+                let mut list = Flex::column().cross_axis_alignment(druid::widget::CrossAxisAlignment::Start);
+
+                if windows.is_empty() {
+                    list.add_child(Label::new("No window available").with_text_color(Color::WHITE));
+                }
+
+                for window in windows.iter() {
+                    let window_id = window.id;
+                    let title = if window.title.is_empty() { window.app_name.clone() } else { window.title.clone() };
+
+                    list.add_child(
+                        Button::new(format!("{}  ({}x{} at {}, {})", title, window.width, window.height, window.x, window.y))
+                            .on_click(move |ctx, data: &mut AppState, _| {
+                                data.set_window_to_capture(Some(window_id));
+                                prepare_for_screenshot(data, ctx);
+                            })
+                            .padding((0.0, 3.0)),
+                    );
+                }
+
+                Box::new(Scroll::new(list).vertical().fix_height(300.0))
+            },
+        );
+
+        let mut title = Label::new("Select the window to capture");
+        title.set_text_size(20.0);
+        title.set_text_color(Color::WHITE);
+
+        FlexMod::column(false)
+            .with_child(title.padding((0.0, 15.0)))
+            .with_child(windows_list)
+            .with_child(Button::new("Cancel").on_click(|_, data: &mut AppState, _| data.set_edit_state(EditState::None)).padding((0.0, 15.0)))
+            .visible_if(|data: &AppState| data.get_edit_state() == EditState::WindowPicking)
+    }
+
     fn build_bottom_page_widget(view_state: &ViewState) -> impl Widget<AppState> {
         match view_state {
             ViewState::MainView => {
@@ -489,6 +1112,7 @@ impl View {
                 );
 
                 FlexMod::column(true)
+                    .with_child(View::build_window_picker())
                     .with_child(screeshot_viewer)
                     .visible_if(|data: &AppState| data.get_view_state() == ViewState::MainView)
                     .center()
@@ -499,12 +1123,20 @@ impl View {
                 let path_menu = MenuOption::build_path_menu_widget();
                 let timer_menu = MenuOption::build_timer_menu();
                 let screen_menu = MenuOption::build_screen_menu();
+                let interval_menu = MenuOption::build_interval_menu();
+                let recording_menu = MenuOption::build_recording_menu();
+                let export_menu = MenuOption::build_export_menu();
+                let presets_menu = MenuOption::build_presets_menu();
                 let menu_options = Scroll::new(
                     Flex::column()
                         .with_child(shortcut_menu)
                         .with_child(path_menu)
                         .with_child(timer_menu)
-                        .with_child(screen_menu),
+                        .with_child(screen_menu)
+                        .with_child(interval_menu)
+                        .with_child(recording_menu)
+                        .with_child(export_menu)
+                        .with_child(presets_menu),
                 )
                 .vertical()
                 .fix_height(500.0);
@@ -667,40 +1299,228 @@ impl MenuOption {
                             .track_color(KeyOrValue::Concrete(Color::TEAL))
                             .knob_style(KnobStyle::Wedge)
                             .axis(druid::widget::Axis::Horizontal)
-                            .with_step(1.0)
-                            .annotated(2.0, 1.0)
+                            .with_step(0.1)
+                            .annotated(2.0, 0.5)
                             .fix_width(250.0)
                             .padding((0.0, 15.0))
                             .lens(AppState::timer))
-            .with_child(Label::new("s").padding(10.0))
+            .with_child(Label::new(|data: &AppState, _: &_| format!("{:.1} s", data.get_timer())).padding(10.0))
             .align_right()
         );
         timer_menu.build()
     }
 
+    fn build_interval_menu() -> impl Widget<AppState> {
+        let mut interval_menu = MenuOption::new("Interval capture".to_string());
+        interval_menu.add_option(
+            "Every".to_string(),
+            Flex::row()
+            .with_child(Slider::new()
+                            .with_range(0.5, 60.0)
+                            .track_color(KeyOrValue::Concrete(Color::TEAL))
+                            .knob_style(KnobStyle::Wedge)
+                            .axis(druid::widget::Axis::Horizontal)
+                            .with_step(0.5)
+                            .fix_width(250.0)
+                            .padding((0.0, 15.0))
+                            .lens(AppState::interval_seconds))
+            .with_child(Label::new(|data: &AppState, _: &_| format!("{:.1} s", data.get_interval_settings().interval_ms as f64 / 1000.0)).padding(10.0))
+            .align_right()
+        );
+        interval_menu.add_option(
+            "Frames".to_string(),
+            Flex::row()
+            .with_child(Slider::new()
+                            .with_range(0.0, 100.0)
+                            .track_color(KeyOrValue::Concrete(Color::TEAL))
+                            .knob_style(KnobStyle::Wedge)
+                            .axis(druid::widget::Axis::Horizontal)
+                            .with_step(1.0)
+                            .fix_width(250.0)
+                            .padding((0.0, 15.0))
+                            .lens(AppState::interval_count))
+            .with_child(Label::new(|data: &AppState, _: &_| match data.get_interval_settings().count {
+                Some(count) => count.to_string(),
+                Option::None => format!("until stopped"),
+            }).padding(10.0))
+            .align_right()
+        );
+        interval_menu.add_option(
+            "Keep newest".to_string(),
+            Flex::row()
+            .with_child(Slider::new()
+                            .with_range(0.0, 100.0)
+                            .track_color(KeyOrValue::Concrete(Color::TEAL))
+                            .knob_style(KnobStyle::Wedge)
+                            .axis(druid::widget::Axis::Horizontal)
+                            .with_step(1.0)
+                            .fix_width(250.0)
+                            .padding((0.0, 15.0))
+                            .lens(AppState::interval_keep_last))
+            .with_child(Label::new(|data: &AppState, _: &_| match data.get_interval_settings().keep_last {
+                Some(keep_last) => format!("{} files", keep_last),
+                Option::None => format!("all files"),
+            }).padding(10.0))
+            .align_right()
+        );
+        interval_menu.build()
+    }
+
+    fn build_recording_menu() -> impl Widget<AppState> {
+        let mut recording_menu = MenuOption::new("Recording".to_string());
+        recording_menu.add_option(
+            "Frame rate".to_string(),
+            Flex::row()
+            .with_child(Slider::new()
+                            .with_range(1.0, 30.0)
+                            .track_color(KeyOrValue::Concrete(Color::TEAL))
+                            .knob_style(KnobStyle::Wedge)
+                            .axis(druid::widget::Axis::Horizontal)
+                            .with_step(1.0)
+                            .fix_width(250.0)
+                            .padding((0.0, 15.0))
+                            .lens(AppState::recording_fps))
+            .with_child(Label::new(|data: &AppState, _: &_| format!("{} fps", data.get_recording_settings().fps)).padding(10.0))
+            .align_right()
+        );
+        recording_menu.add_option(
+            "Max duration".to_string(),
+            Flex::row()
+            .with_child(Slider::new()
+                            .with_range(1.0, 60.0)
+                            .track_color(KeyOrValue::Concrete(Color::TEAL))
+                            .knob_style(KnobStyle::Wedge)
+                            .axis(druid::widget::Axis::Horizontal)
+                            .with_step(1.0)
+                            .fix_width(250.0)
+                            .padding((0.0, 15.0))
+                            .lens(AppState::recording_max_seconds))
+            .with_child(Label::new(|data: &AppState, _: &_| format!("{} s", data.get_recording_settings().max_duration_ms / 1000)).padding(10.0))
+            .align_right()
+        );
+        recording_menu.add_option(
+            "Format".to_string(),
+            RadioGroup::row(vec![
+                ("GIF".to_string(), RecordingFormat::Gif),
+                ("APNG".to_string(), RecordingFormat::Apng),
+            ])
+            .lens(AppState::recording_format),
+        );
+        recording_menu.add_option(
+            "Region".to_string(),
+            Checkbox::new("Only the last cropped region")
+                .lens(AppState::recording_region)
+                .disabled_if(|data: &AppState, _| !data.has_last_region()),
+        );
+        recording_menu.build()
+    }
+
+    fn build_presets_menu() -> impl Widget<AppState> {
+        let mut presets_menu = MenuOption::new("Selection presets".to_string());
+        presets_menu.add_option(
+            "Selection".to_string(),
+            ViewSwitcher::new(
+                |data: &AppState, _| data.get_presets(),
+                |presets, _, _| {
+                    let mut choices = vec![("Free".to_string(), Option::None)];
+                    for (i, preset) in presets.iter().enumerate() {
+                        let kind = if preset.fixed_size { format!("{}x{}", preset.width, preset.height) } else { format!("{}:{}", preset.width, preset.height) };
+                        choices.push((format!("{} ({})", preset.name, kind), Some(i)));
+                    }
+                    Box::new(RadioGroup::column(choices).lens(AppState::preset))
+                },
+            ),
+        );
+        presets_menu.add_option(
+            "Edit".to_string(),
+            ViewSwitcher::new(
+                |data: &AppState, _| data.get_presets().len(),
+                |count, _, _| {
+                    let mut rows = Flex::column().cross_axis_alignment(druid::widget::CrossAxisAlignment::End);
+                    for i in 0..*count {
+                        rows.add_child(
+                            Flex::row()
+                                // the presets are saved when the edit is committed (Enter, Tab or focus lost), not at every key stroke
+                                .with_child(TextBox::new().with_formatter(druid::text::ParseFormatter::new()).fix_width(120.0).lens(preset_lens(i).then(CapturePreset::name)))
+                                .with_child(TextBox::new().with_formatter(druid::text::ParseFormatter::new()).fix_width(60.0).lens(preset_lens(i).then(CapturePreset::width)).padding((5.0, 0.0)))
+                                .with_child(Label::new("x"))
+                                .with_child(TextBox::new().with_formatter(druid::text::ParseFormatter::new()).fix_width(60.0).lens(preset_lens(i).then(CapturePreset::height)).padding((5.0, 0.0)))
+                                .with_child(Checkbox::new("Fixed size").lens(preset_lens(i).then(CapturePreset::fixed_size)))
+                                .with_child(Button::new("Delete").on_click(move |_, data: &mut AppState, _| data.remove_preset(i)).padding((5.0, 0.0)))
+                                .padding((0.0, 5.0))
+                        );
+                    }
+                    rows.add_child(Button::new("Add preset").on_click(|_, data: &mut AppState, _| data.add_preset()));
+                    Box::new(rows)
+                },
+            ),
+        );
+        presets_menu.build()
+    }
+
+    fn build_export_menu() -> impl Widget<AppState> {
+        let mut export_menu = MenuOption::new("Export".to_string());
+        export_menu.add_option(
+            "Resolution".to_string(),
+            Flex::row()
+            .with_child(RadioGroup::row(vec![
+                ("Physical".to_string(), ExportResolution::Physical),
+                ("Logical".to_string(), ExportResolution::Logical),
+            ])
+            .lens(AppState::export_resolution))
+            .with_child(Label::new(|data: &AppState, _: &_| format!("scale {}x", data.get_scale_factor())).padding(10.0))
+            .align_right()
+        );
+        export_menu.build()
+    }
+
     fn build_screen_menu() -> impl Widget<AppState> {
         let mut screen_menu = MenuOption::new("Screen".to_string());
-        let mut screen_indexes = vec![];
-        for i in 0..number_of_screens() {
-            screen_indexes.push(((i+1).to_string(), i));
-        }
-        screen_menu.add_option("Index".to_string(), RadioGroup::row(screen_indexes).lens(AppState::screen_index));
+        screen_menu.add_option(
+            "Index".to_string(),
+            ViewSwitcher::new(
+                |data: &AppState, _| data.get_screens(),
+                |screens, _, _| {
+                    let mut screen_indexes = vec![];
+                    for (i, screen) in screens.iter().enumerate() {
+                        let mut label = format!("{}: {}x{}", i + 1, screen.width, screen.height);
+                        if screen.scale_factor != 1.0 {
+                            label.push_str(&format!(" @{}x", screen.scale_factor));
+                        }
+                        if screen.is_primary {
+                            label.push_str(" (primary)");
+                        }
+                        screen_indexes.push((label, i));
+                    }
+                    screen_indexes.push(("All".to_string(), ALL_SCREENS));
+                    Box::new(RadioGroup::row(screen_indexes).lens(AppState::screen_index))
+                },
+            ),
+        );
+        screen_menu.add_option(
+            "Mouse pointer".to_string(),
+            Checkbox::new("Include in captures").lens(druid::lens::Map::new(
+                |data: &AppState| data.get_include_cursor(),
+                |data: &mut AppState, include_cursor| data.set_include_cursor(include_cursor),
+            )),
+        );
         screen_menu.build()
     }
 }
 
+/// Return the lens to the preset with the given index, the presets are persisted
+/// when a change reaches them: a committed text edit, the checkbox or a button
+fn preset_lens(index: usize) -> impl druid::Lens<AppState, CapturePreset> {
+    druid::lens::Map::new(
+        |data: &AppState| data.get_presets(),
+        |data: &mut AppState, presets| data.set_presets(presets),
+    )
+    .then(druid::lens::Index::new(index))
+}
+
 fn prepare_for_screenshot(data: &mut AppState, ctx: &mut druid::EventCtx) {
-    let mut win = ctx.window().clone();
-    
-    if win.get_window_state() != druid::WindowState::Minimized{
-        win.set_window_state(druid::WindowState::Minimized);    
-    }
-    
-    data.reset_img();
     data.set_edit_state(EditState::None);
-    
-    let token = ctx.request_timer(Duration::from_millis(500));
-    data.set_screenshot_token(token.into_raw());
+    data.start_screenshot(ctx.get_external_handle());
 }
 
 struct WindowController;
@@ -717,6 +1537,28 @@ impl<W: Widget<AppState>> Controller<AppState, W> for WindowController {
             Event::Command(ref c) => {
                 if c.is(Selector::<()>::new("new_screenshot")){
                     prepare_for_screenshot(data, ctx);
+                }else if c.is(Selector::<()>::new("capture_last_region")) {
+                    data.set_edit_state(EditState::None);
+                    data.start_last_region_screenshot(ctx.get_external_handle());
+                }else if c.is(Selector::<()>::new("minimize")) {
+                    let mut win = ctx.window().clone();
+                    if win.get_window_state() != druid::WindowState::Minimized{
+                        win.set_window_state(druid::WindowState::Minimized);
+                    }
+                }else if c.is(Selector::<()>::new("cancel_screenshot")) {
+                    data.stop_capture();
+                }else if c.is(Selector::<()>::new("open_region_overlay")) {
+                    if let Some(captured) = data.get_frozen_capture() {
+                        let (x, y, width, height) = data.get_region_bounds();
+                        let origin = ((x as f64 * captured.scale_factor).round() as i32, (y as f64 * captured.scale_factor).round() as i32);
+                        let overlay = WindowDesc::new(RegionOverlay::new(captured.image, data.get_area_constraint(), origin))
+                            .show_titlebar(false)
+                            .resizable(false)
+                            .set_always_on_top(true)
+                            .set_position((x as f64, y as f64))
+                            .window_size((width as f64, height as f64));
+                        ctx.new_window(overlay);
+                    }
                 }else if c.is(Selector::<()>::new("restore")) {
                     let mut win = ctx.window().clone();
                     win.set_window_state(druid::WindowState::Restored);
@@ -0,0 +1,348 @@
+pub mod region_overlay {
+    use druid::{
+        kurbo::{Line, Rect},
+        piet::{FontFamily, InterpolationMode, PietImage, Text, TextLayoutBuilder},
+        BoxConstraints, Color, Command, Cursor, Env, Event, ImageBuf, LayoutCtx, PaintCtx, Point,
+        RenderContext, Selector, Size, Target, Widget,
+    };
+    use event_lib::AppState;
+    use screenshot_lib::{calculate_area_in_bounds, Area, AreaConstraint};
+
+    const SHADE_COLOR: Color = Color::rgba8(0, 0, 0, 120);
+    const SELECTION_COLOR: Color = Color::rgb8(0x00, 0x80, 0x80);
+    const CROSSHAIR_COLOR: Color = Color::rgba8(255, 255, 255, 160);
+
+    /// Fullscreen widget that shows a frozen capture and lets the user select
+    /// a region of it: drag to select, Enter to accept, Esc to cancel.
+    /// Arrow keys move the bottom right edges of the selection,
+    /// with Shift they move the top left ones. The selection respects
+    /// the constraint of the selected preset, a fixed size frame is moved by the keys
+    pub struct RegionOverlay {
+        image_data: ImageBuf,
+        constraint: AreaConstraint,
+        /// Position of the top left corner of the image in pixels of the virtual desktop,
+        /// it is negative when the capture starts on a screen on the left or above the main one
+        origin: (i32, i32),
+        paint_data: Option<PietImage>,
+        /// Corners of the selection in pixels of the virtual desktop
+        start_point: Option<(i32, i32)>,
+        end_point: (i32, i32),
+        dragging: bool,
+        mouse_pos: Point,
+        widget_size: Size,
+    }
+
+    impl RegionOverlay {
+        pub fn new(image_data: ImageBuf, constraint: AreaConstraint, origin: (i32, i32)) -> Self {
+            RegionOverlay {
+                image_data,
+                constraint,
+                origin,
+                paint_data: None,
+                start_point: None,
+                end_point: (0, 0),
+                dragging: false,
+                mouse_pos: Point::ZERO,
+                widget_size: Size::ZERO,
+            }
+        }
+
+        /// Return the ratio between the pixels of the image and the widget
+        fn ratio(&self) -> (f64, f64) {
+            if self.widget_size.is_empty() {
+                return (1.0, 1.0);
+            }
+
+            (
+                self.image_data.width() as f64 / self.widget_size.width,
+                self.image_data.height() as f64 / self.widget_size.height,
+            )
+        }
+
+        /// Return the point of the virtual desktop under the position of the widget
+        fn to_desktop_point(&self, pos: Point) -> (i32, i32) {
+            let (ratio_x, ratio_y) = self.ratio();
+
+            (
+                self.origin.0 + (pos.x * ratio_x).round() as i32,
+                self.origin.1 + (pos.y * ratio_y).round() as i32,
+            )
+        }
+
+        /// Return the rect of the image in pixels of the virtual desktop
+        fn bounds(&self) -> (i32, i32, u32, u32) {
+            (
+                self.origin.0,
+                self.origin.1,
+                self.image_data.width() as u32,
+                self.image_data.height() as u32,
+            )
+        }
+
+        /// Return the selected area relative to the image, clamped to it
+        /// also when the selection spans more screens
+        fn selection(&self) -> Option<Area> {
+            let start_point = self.start_point?;
+
+            match calculate_area_in_bounds(
+                self.bounds(),
+                start_point,
+                self.end_point,
+                self.constraint,
+            ) {
+                Some(area) if area.width > 0 && area.height > 0 => Some(area),
+                _ => None,
+            }
+        }
+
+        /// Move the edges of the selection by the given amount of pixels
+        fn nudge(&mut self, dx: i32, dy: i32, top_left: bool) {
+            let area = match self.selection() {
+                Some(area) => area,
+                None => return,
+            };
+
+            // a fixed size frame is centred on the end point, so moving it moves the frame
+            if let AreaConstraint::FixedSize(_, _) = self.constraint {
+                self.end_point = (
+                    self.origin.0 + area.left_corner.0 as i32 + area.width as i32 / 2 + dx,
+                    self.origin.1 + area.left_corner.1 as i32 + area.height as i32 / 2 + dy,
+                );
+                return;
+            }
+
+            // the corners are normalized, so that the edges moved by the keys are always the same
+            let (x, y, width, height) = self.bounds();
+            let mut start_point = (x + area.left_corner.0 as i32, y + area.left_corner.1 as i32);
+            let mut end_point = (
+                start_point.0 + area.width as i32,
+                start_point.1 + area.height as i32,
+            );
+
+            if top_left {
+                start_point = (
+                    (start_point.0 + dx).clamp(x, end_point.0 - 1),
+                    (start_point.1 + dy).clamp(y, end_point.1 - 1),
+                );
+            } else {
+                end_point = (
+                    (end_point.0 + dx).clamp(start_point.0 + 1, x + width as i32),
+                    (end_point.1 + dy).clamp(start_point.1 + 1, y + height as i32),
+                );
+            }
+
+            // with a locked ratio the other side follows the moved edge
+            if let AreaConstraint::AspectRatio(ratio_width, ratio_height) = self.constraint {
+                let (mut width, mut height) =
+                    (end_point.0 - start_point.0, end_point.1 - start_point.1);
+
+                if dx != 0 {
+                    height = width * ratio_height as i32 / ratio_width.max(1) as i32;
+                } else {
+                    width = height * ratio_width as i32 / ratio_height.max(1) as i32;
+                }
+
+                if top_left {
+                    start_point = (end_point.0 - width, end_point.1 - height);
+                } else {
+                    end_point = (start_point.0 + width, start_point.1 + height);
+                }
+            }
+
+            self.start_point = Some(start_point);
+            self.end_point = end_point;
+        }
+
+        /// Close the overlay, loading the selected area in the editor if `accept` is set
+        fn close(&mut self, ctx: &mut druid::EventCtx, data: &mut AppState, accept: bool) {
+            let area = match accept {
+                true => self.selection(),
+                false => None,
+            };
+
+            data.finish_region_selection(area);
+
+            ctx.submit_command(Command::new(Selector::new("restore"), (), Target::Global));
+            ctx.window().close();
+        }
+    }
+
+    impl Widget<AppState> for RegionOverlay {
+        fn event(
+            &mut self,
+            ctx: &mut druid::EventCtx,
+            event: &Event,
+            data: &mut AppState,
+            _env: &Env,
+        ) {
+            match event {
+                Event::WindowConnected => {
+                    ctx.request_focus();
+                    ctx.set_cursor(&Cursor::Crosshair);
+                }
+                Event::MouseDown(mouse_event) => {
+                    let point = self.to_desktop_point(mouse_event.pos);
+
+                    self.start_point = Some(point);
+                    self.end_point = point;
+                    self.dragging = true;
+                    ctx.set_active(true);
+                    ctx.request_paint();
+                }
+                Event::MouseMove(mouse_event) => {
+                    ctx.set_cursor(&Cursor::Crosshair);
+                    self.mouse_pos = mouse_event.pos;
+
+                    if self.dragging {
+                        self.end_point = self.to_desktop_point(mouse_event.pos);
+                    }
+
+                    ctx.request_paint();
+                }
+                Event::MouseUp(mouse_event) => {
+                    if self.dragging {
+                        self.end_point = self.to_desktop_point(mouse_event.pos);
+                        self.dragging = false;
+                        ctx.set_active(false);
+
+                        if self.selection().is_none() {
+                            self.start_point = None;
+                        }
+                    }
+
+                    ctx.request_paint();
+                }
+                Event::KeyDown(key_event) => {
+                    let step = if key_event.mods.ctrl() { 10 } else { 1 };
+                    let top_left = key_event.mods.shift();
+
+                    match &key_event.key {
+                        druid::keyboard_types::Key::Escape => self.close(ctx, data, false),
+                        druid::keyboard_types::Key::Enter => {
+                            if self.selection().is_some() {
+                                self.close(ctx, data, true);
+                            }
+                        }
+                        druid::keyboard_types::Key::ArrowLeft => self.nudge(-step, 0, top_left),
+                        druid::keyboard_types::Key::ArrowRight => self.nudge(step, 0, top_left),
+                        druid::keyboard_types::Key::ArrowUp => self.nudge(0, -step, top_left),
+                        druid::keyboard_types::Key::ArrowDown => self.nudge(0, step, top_left),
+                        _ => {}
+                    }
+
+                    ctx.request_paint();
+                }
+                _ => {}
+            }
+        }
+
+        fn lifecycle(
+            &mut self,
+            _ctx: &mut druid::LifeCycleCtx,
+            _event: &druid::LifeCycle,
+            _data: &AppState,
+            _env: &Env,
+        ) {
+        }
+
+        fn update(
+            &mut self,
+            _ctx: &mut druid::UpdateCtx,
+            _old_data: &AppState,
+            _data: &AppState,
+            _env: &Env,
+        ) {
+        }
+
+        fn layout(
+            &mut self,
+            _layout_ctx: &mut LayoutCtx,
+            bc: &BoxConstraints,
+            _data: &AppState,
+            _env: &Env,
+        ) -> Size {
+            self.widget_size = bc.max();
+
+            return self.widget_size;
+        }
+
+        fn paint(&mut self, ctx: &mut PaintCtx, _data: &AppState, _env: &Env) {
+            let size = ctx.size();
+            let full_rect = size.to_rect();
+
+            let piet_image = {
+                let image_data = &self.image_data;
+                self.paint_data
+                    .get_or_insert_with(|| image_data.to_image(ctx.render_ctx))
+            };
+            ctx.draw_image(piet_image, full_rect, InterpolationMode::Bilinear);
+
+            let (ratio_x, ratio_y) = self.ratio();
+
+            match self.selection() {
+                Some(area) => {
+                    let selection_rect = Rect::new(
+                        area.left_corner.0 as f64 / ratio_x,
+                        area.left_corner.1 as f64 / ratio_y,
+                        (area.left_corner.0 + area.width) as f64 / ratio_x,
+                        (area.left_corner.1 + area.height) as f64 / ratio_y,
+                    );
+
+                    // shade everything but the selection
+                    ctx.fill(Rect::new(0.0, 0.0, size.width, selection_rect.y0), &SHADE_COLOR);
+                    ctx.fill(
+                        Rect::new(0.0, selection_rect.y1, size.width, size.height),
+                        &SHADE_COLOR,
+                    );
+                    ctx.fill(
+                        Rect::new(0.0, selection_rect.y0, selection_rect.x0, selection_rect.y1),
+                        &SHADE_COLOR,
+                    );
+                    ctx.fill(
+                        Rect::new(
+                            selection_rect.x1,
+                            selection_rect.y0,
+                            size.width,
+                            selection_rect.y1,
+                        ),
+                        &SHADE_COLOR,
+                    );
+                    ctx.stroke(selection_rect, &SELECTION_COLOR, 2.0);
+
+                    // the size label is skipped if the text can not be laid out
+                    let label = format!("{} x {}", area.width, area.height);
+                    if let Ok(layout) = ctx
+                        .text()
+                        .new_text_layout(label)
+                        .font(FontFamily::SYSTEM_UI, 14.0)
+                        .text_color(Color::WHITE)
+                        .build()
+                    {
+                        let label_pos = Point::new(
+                            selection_rect.x0,
+                            if selection_rect.y0 > 20.0 {
+                                selection_rect.y0 - 20.0
+                            } else {
+                                selection_rect.y1 + 4.0
+                            },
+                        );
+                        ctx.draw_text(&layout, label_pos);
+                    }
+                }
+                None => ctx.fill(full_rect, &SHADE_COLOR),
+            }
+
+            // crosshair on the mouse pointer
+            ctx.stroke(
+                Line::new((self.mouse_pos.x, 0.0), (self.mouse_pos.x, size.height)),
+                &CROSSHAIR_COLOR,
+                1.0,
+            );
+            ctx.stroke(
+                Line::new((0.0, self.mouse_pos.y), (size.width, self.mouse_pos.y)),
+                &CROSSHAIR_COLOR,
+                1.0,
+            );
+        }
+    }
+}
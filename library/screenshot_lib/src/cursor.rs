@@ -0,0 +1,69 @@
+pub mod cursor {
+    use druid::{piet::ImageFormat, ImageBuf};
+
+    /// Built-in arrow sprite: `X` is the black outline, `.` the white fill
+    /// and a space is transparent. The hot spot is the top left corner
+    const ARROW: [&str; 19] = [
+        "X           ",
+        "XX          ",
+        "X.X         ",
+        "X..X        ",
+        "X...X       ",
+        "X....X      ",
+        "X.....X     ",
+        "X......X    ",
+        "X.......X   ",
+        "X........X  ",
+        "X.........X ",
+        "X......XXXXX",
+        "X...X..X    ",
+        "X..XX..X    ",
+        "X.X  X..X   ",
+        "XX   X..X   ",
+        "X     X..X  ",
+        "      X..X  ",
+        "       XX   ",
+    ];
+
+    /// Return a copy of the image with the arrow sprite drawn with its hot spot
+    /// at `position`, expressed in pixels of the image. The sprite is scaled by
+    /// `scale_factor`, so that it has the same size the user sees on the screen.
+    /// The parts of the sprite outside the image are clipped
+    pub fn draw_cursor(image: &ImageBuf, position: (i32, i32), scale_factor: f64) -> ImageBuf {
+        let (width, height) = (image.width() as i32, image.height() as i32);
+        let scale_factor = scale_factor.max(1.0);
+        let sprite_width = (ARROW[0].len() as f64 * scale_factor).round() as i32;
+        let sprite_height = (ARROW.len() as f64 * scale_factor).round() as i32;
+        let mut container = image.raw_pixels().to_vec();
+
+        for r in 0..sprite_height {
+            let row = (r as f64 / scale_factor) as usize;
+            let y = position.1 + r;
+
+            for c in 0..sprite_width {
+                let col = (c as f64 / scale_factor) as usize;
+                let x = position.0 + c;
+
+                if x < 0 || y < 0 || x >= width || y >= height {
+                    continue;
+                }
+
+                let color: u8 = match ARROW[row].as_bytes()[col] {
+                    b'X' => 0,
+                    b'.' => 255,
+                    _ => continue,
+                };
+
+                let i = ((y * width + x) * 4) as usize;
+                container[i..i + 4].copy_from_slice(&[color, color, color, 255]);
+            }
+        }
+
+        return ImageBuf::from_raw(
+            container,
+            ImageFormat::RgbaSeparate,
+            width as usize,
+            height as usize,
+        );
+    }
+}
@@ -0,0 +1,207 @@
+pub mod interval {
+    use crate::{
+        source::source::CaptureSource, take_scaled_screenshot, wait_with_cancel, CaptureError,
+        CaptureTarget,
+    };
+    use druid::ImageBuf;
+    use image::RgbaImage;
+    use std::{
+        collections::VecDeque,
+        fs::remove_file,
+        path::{Path, PathBuf},
+        sync::atomic::AtomicBool,
+        time::{Duration, Instant},
+    };
+
+    /// Settings of an interval capture
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct IntervalSettings {
+        /// Time between two frames, in milliseconds
+        pub interval_ms: u64,
+        /// Number of frames to capture, `None` means until stopped
+        pub count: Option<u32>,
+        /// Number of newest files to keep on disk, `None` means keep all the files
+        pub keep_last: Option<u32>,
+        /// Draw the mouse pointer on the frames
+        pub include_cursor: bool,
+    }
+
+    /// Outcome of an interval capture
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    pub struct IntervalReport {
+        /// Number of frames written to disk
+        pub saved: u32,
+        /// Number of frames skipped because they could not be captured or saved
+        pub failed: u32,
+        /// Error of the last skipped frame, if any
+        pub last_error: Option<CaptureError>,
+    }
+
+    /// Return the path of the frame with the given sequence number,
+    /// for example `dir/base_name 0001.png`
+    pub fn frame_path(dir: &Path, base_name: &str, sequence: u32, extension: &str) -> PathBuf {
+        let mut path = dir.to_path_buf();
+
+        path.push(format!("{} {:04}", base_name, sequence));
+        path.set_extension(extension);
+
+        return path;
+    }
+
+    /// Save an `ImageBuf` in RGBA format to the given path,
+    /// the format of the file is chosen from its extension
+    pub fn save_frame(image: &ImageBuf, path: &Path) -> Result<(), CaptureError> {
+        let img = match RgbaImage::from_raw(
+            image.width() as u32,
+            image.height() as u32,
+            image.raw_pixels().to_vec(),
+        ) {
+            Some(img) => img,
+            None => return Err(CaptureError::SaveFailed(format!("invalid image buffer"))),
+        };
+
+        img.save(path)
+            .map_err(|e| CaptureError::SaveFailed(format!("{}: {}", path.display(), e)))
+    }
+
+    /// This function captures `target` every `settings.interval_ms` milliseconds and
+    /// writes each frame straight to `dir`, with a sequence number in the file name.
+    /// If `settings.keep_last` is set only the newest files written by this run are kept.
+    /// After every frame `progress` is called with the number of frames written.
+    /// A frame that can not be captured or saved is skipped without stopping the run.
+    /// The capture stops after `settings.count` frames or when `cancelled` is set,
+    /// and the number of frames written and skipped is returned.
+    /// It blocks the calling thread, so it has to be called from a worker thread.
+    pub fn capture_interval(
+        source: &dyn CaptureSource,
+        target: &CaptureTarget,
+        settings: &IntervalSettings,
+        dir: &Path,
+        base_name: &str,
+        extension: &str,
+        cancelled: &AtomicBool,
+        mut progress: impl FnMut(u32),
+    ) -> IntervalReport {
+        let start = Instant::now();
+        let mut written: VecDeque<PathBuf> = VecDeque::new();
+        let mut report = IntervalReport::default();
+        let mut sequence = 0;
+
+        loop {
+            sequence += 1;
+
+            // the files are numbered by the frames written, so that a skipped frame leaves no gap
+            let path = frame_path(dir, base_name, report.saved + 1, extension);
+            let saved = take_scaled_screenshot(source, target, settings.include_cursor)
+                .and_then(|captured| save_frame(&captured.image, &path));
+
+            match saved {
+                Ok(_) => {
+                    report.saved += 1;
+                    written.push_back(path);
+
+                    if let Some(keep_last) = settings.keep_last {
+                        while written.len() > keep_last.max(1) as usize {
+                            let oldest = written.pop_front().unwrap();
+                            let _ = remove_file(oldest);
+                        }
+                    }
+
+                    progress(report.saved);
+                }
+                Err(err) => {
+                    report.failed += 1;
+                    report.last_error = Some(err);
+                }
+            }
+
+            if let Some(count) = settings.count {
+                if sequence >= count {
+                    break;
+                }
+            }
+
+            // the deadline of each frame is computed from the start, so that the delays don't accumulate
+            let deadline = Duration::from_millis(settings.interval_ms * sequence as u64);
+            let remaining = deadline.saturating_sub(start.elapsed());
+            if wait_with_cancel(remaining.as_millis() as u64, cancelled).is_err() {
+                break;
+            }
+        }
+
+        return report;
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::source::source::{screens_dir, FileSource, ScreenInfo};
+        use std::{
+            fs,
+            sync::atomic::{AtomicU32, Ordering},
+        };
+
+        /// Source that fails the capture of every second frame
+        struct FlakySource {
+            inner: FileSource,
+            captures: AtomicU32,
+        }
+
+        impl CaptureSource for FlakySource {
+            fn screens(&self) -> Result<Vec<ScreenInfo>, CaptureError> {
+                self.inner.screens()
+            }
+
+            fn capture(&self, index: usize) -> Result<ImageBuf, CaptureError> {
+                if self.captures.fetch_add(1, Ordering::SeqCst) % 2 == 1 {
+                    return Err(CaptureError::CaptureFailed(format!("busy")));
+                }
+
+                self.inner.capture(index)
+            }
+        }
+
+        #[test]
+        fn failed_frames_are_skipped_and_counted() {
+            let dir = screens_dir("interval_flaky", &[(4, 3, [0, 255, 0, 255])]);
+            let source = FlakySource {
+                inner: FileSource::new(&dir),
+                captures: AtomicU32::new(0),
+            };
+            let out = std::env::temp_dir().join(format!("interval_out_{}", std::process::id()));
+            let _ = fs::remove_dir_all(&out);
+            fs::create_dir_all(&out).unwrap();
+
+            let settings = IntervalSettings {
+                interval_ms: 0,
+                count: Some(5),
+                keep_last: None,
+                include_cursor: false,
+            };
+            let mut progress = vec![];
+            let report = capture_interval(
+                &source,
+                &CaptureTarget::Screen(0),
+                &settings,
+                &out,
+                "frame",
+                "png",
+                &AtomicBool::new(false),
+                |saved| progress.push(saved),
+            );
+
+            assert_eq!(report.saved, 3);
+            assert_eq!(report.failed, 2);
+            assert_eq!(
+                report.last_error,
+                Some(CaptureError::CaptureFailed(format!("busy")))
+            );
+            assert_eq!(progress, vec![1, 2, 3]);
+            assert!(frame_path(&out, "frame", 3, "png").exists());
+            assert!(!frame_path(&out, "frame", 4, "png").exists());
+
+            fs::remove_dir_all(out).unwrap();
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
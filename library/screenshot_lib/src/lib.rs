@@ -1,6 +1,20 @@
+pub mod cursor;
+pub mod interval;
+pub mod recording;
+pub mod source;
+use cursor::cursor::draw_cursor;
 use druid::{Data, ImageBuf, Size};
-use screenshots::Screen;
-use std::{thread, time::Duration};
+use source::source::*;
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Screen index used to request a capture of all the screens,
+/// stitched together in a single image of the whole virtual desktop
+pub const ALL_SCREENS: usize = usize::MAX;
 
 #[derive(Clone, Debug, PartialEq, Eq, Data)]
 pub struct Area {
@@ -9,6 +23,34 @@ pub struct Area {
     pub height: u32,
 }
 
+/// Constraint applied to the area selected by the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Data)]
+pub enum AreaConstraint {
+    /// The area follows the drag
+    Free,
+    /// The area has a fixed `(width, height)` and it is centred on the end of the drag,
+    /// so that dragging moves the frame
+    FixedSize(u32, u32),
+    /// The area is the biggest one with the ratio `width:height`
+    /// inside the rect from the start to the end of the drag
+    AspectRatio(u32, u32),
+}
+
+/// A captured image in physical pixels, with the scale factor of the screen it comes from.
+/// The logical size of the image is the physical one divided by `scale_factor`
+#[derive(Clone, Data)]
+pub struct CapturedImage {
+    pub image: ImageBuf,
+    pub scale_factor: f64,
+}
+
+/// What has to be captured: a screen by index (or `ALL_SCREENS`) or a window by id
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaptureTarget {
+    Screen(usize),
+    Window(u32),
+}
+
 impl Area {
     pub fn new() -> Area {
         Area {
@@ -19,24 +61,296 @@ impl Area {
     }
 }
 
-/// This function recieve the current screen on witch the screenshot has to be taken,
-/// then it saves a screenshot of the whole selected screen in a ImageBuffer.
-fn take_screenshot(current_screen: usize) -> Option<ImageBuf> {
-    let screens = Screen::all().unwrap();
-    let current_screen = screens[current_screen];
-    let screen_infos = current_screen.display_info;
+/// This enum represents the reasons for which a capture can fail
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaptureError {
+    /// No display is available or the list of screens can not be retrieved
+    NoDisplay(String),
+    /// The OS has denied the permission to record the screen
+    PermissionDenied(String),
+    /// The requested screen index does not exist (e.g. the monitor has been unplugged)
+    ScreenNotFound(usize),
+    /// The pending capture has been cancelled by the user
+    Cancelled,
+    /// The captured image can not be written to disk
+    SaveFailed(String),
+    /// The requested window does not exist anymore (e.g. it has been closed)
+    WindowNotFound(u32),
+    /// The capture of the screen has failed for any other reason
+    CaptureFailed(String),
+    /// The requested area does not fit inside the captured image
+    AreaOutOfBounds(Area),
+}
 
-    let image = current_screen.capture().unwrap();
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::NoDisplay(msg) => write!(f, "No display available: {}", msg),
+            CaptureError::PermissionDenied(msg) => {
+                write!(f, "Permission to record the screen denied: {}", msg)
+            }
+            CaptureError::ScreenNotFound(index) => {
+                write!(f, "Screen {} not found, it may have been disconnected", index + 1)
+            }
+            CaptureError::SaveFailed(msg) => write!(f, "Unable to save the capture: {}", msg),
+            CaptureError::Cancelled => write!(f, "The screenshot has been cancelled"),
+            CaptureError::WindowNotFound(id) => {
+                write!(f, "Window {} not found, it may have been closed", id)
+            }
+            CaptureError::CaptureFailed(msg) => write!(f, "Unable to capture the screen: {}", msg),
+            CaptureError::AreaOutOfBounds(area) => write!(
+                f,
+                "The area {}x{} at ({}, {}) does not fit inside the screen",
+                area.width, area.height, area.left_corner.0, area.left_corner.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl CaptureError {
+    /// Translate an error returned by the capture of a screen to `CaptureError`,
+    /// distinguishing the errors caused by a missing permission
+    fn from_capture(msg: String) -> CaptureError {
+        let lowercase = msg.to_lowercase();
 
-    let img_vec = image.clone().to_vec();
+        if lowercase.contains("permission") || lowercase.contains("denied") {
+            CaptureError::PermissionDenied(msg)
+        } else {
+            CaptureError::CaptureFailed(msg)
+        }
+    }
+}
+
+/// This function recieve the target on witch the screenshot has to be taken,
+/// then it saves a screenshot of the whole selected screen or window in a ImageBuffer.
+pub fn take_screenshot(
+    source: &dyn CaptureSource,
+    target: &CaptureTarget,
+) -> Result<ImageBuf, CaptureError> {
+    match target {
+        CaptureTarget::Screen(ALL_SCREENS) => take_screenshot_all_screens(source),
+        CaptureTarget::Screen(index) => source.capture(*index),
+        CaptureTarget::Window(id) => source.capture_window(*id),
+    }
+}
+
+/// This function captures every screen and places each capture on a single canvas
+/// sized to the union of all screen rects, using the offsets of each screen.
+/// Screens with a negative origin (e.g. on the left or above the main one) are handled
+/// by translating everything by the top left corner of the virtual desktop.
+/// The offsets are logical, so they are converted to pixels with the highest
+/// scale factor among the screens, the same returned by `scale_factor_of`.
+/// The captures of screens with a lower scale factor are scaled up to fill their place.
+fn take_screenshot_all_screens(source: &dyn CaptureSource) -> Result<ImageBuf, CaptureError> {
+    let screens = source.screens()?;
+    let scale_factor = max_scale_factor(&screens);
+    let (min_x, min_y, width, height) = bounds_of_screens(&screens);
+    let width = (width as f64 * scale_factor).round() as u32;
+    let height = (height as f64 * scale_factor).round() as u32;
+    let mut container: Vec<u8> = vec![0; width as usize * height as usize * 4];
+
+    for (index, screen_infos) in screens.iter().enumerate() {
+        let slot_width = (screen_infos.width as f64 * scale_factor).round() as u32;
+        let slot_height = (screen_infos.height as f64 * scale_factor).round() as u32;
+        let image = scale_to_size(&source.capture(index)?, slot_width, slot_height);
+
+        let offset_c = ((screen_infos.x - min_x) as f64 * scale_factor).round() as usize;
+        let offset_r = ((screen_infos.y - min_y) as f64 * scale_factor).round() as usize;
+        // rounding can move an offset to the edge of the canvas
+        let img_width = image.width().min((width as usize).saturating_sub(offset_c));
+        let img_height = image
+            .height()
+            .min((height as usize).saturating_sub(offset_r));
+        let img_vec = image.raw_pixels();
+
+        for r in 0..img_height {
+            let from = r * image.width() * 4;
+            let to = from + img_width * 4;
+            let dest = ((r + offset_r) * width as usize + offset_c) * 4;
+
+            container[dest..dest + img_width * 4].copy_from_slice(&img_vec[from..to]);
+        }
+    }
 
     let image_view = ImageBuf::from_raw(
-        img_vec,
+        container,
+        druid::piet::ImageFormat::RgbaSeparate,
+        width as usize,
+        height as usize,
+    );
+    return Ok(image_view);
+}
+
+/// Return a copy of the image resized to `width` x `height` pixels,
+/// the image is returned as it is if it has already that size
+fn scale_to_size(image: &ImageBuf, width: u32, height: u32) -> ImageBuf {
+    let same_size = (image.width() as u32, image.height() as u32) == (width, height);
+    if same_size || width == 0 || height == 0 {
+        return image.clone();
+    }
+
+    let buffer = match image::RgbaImage::from_raw(
+        image.width() as u32,
+        image.height() as u32,
+        image.raw_pixels().to_vec(),
+    ) {
+        Some(buffer) => buffer,
+        None => return image.clone(),
+    };
+    let resized = image::imageops::resize(
+        &buffer,
+        width,
+        height,
+        image::imageops::FilterType::Triangle,
+    );
+
+    return ImageBuf::from_raw(
+        resized.into_raw(),
         druid::piet::ImageFormat::RgbaSeparate,
-        screen_infos.width as usize,
-        screen_infos.height as usize,
+        width as usize,
+        height as usize,
+    );
+}
+
+/// Return the highest scale factor among the given screens, 1.0 if there is no screen
+fn max_scale_factor(screens: &Vec<ScreenInfo>) -> f64 {
+    screens
+        .iter()
+        .map(|s| s.scale_factor)
+        .fold(1.0, |max, scale_factor| max.max(scale_factor))
+}
+
+/// Return the scale factor of the images captured from `target`:
+/// the one of the screen, the highest one for `ALL_SCREENS`
+/// and the one of the monitor of the window for a window
+pub fn scale_factor_of(
+    source: &dyn CaptureSource,
+    target: &CaptureTarget,
+) -> Result<f64, CaptureError> {
+    match target {
+        CaptureTarget::Screen(ALL_SCREENS) => Ok(max_scale_factor(&source.screens()?)),
+        CaptureTarget::Screen(index) => match source.screens()?.get(*index) {
+            Some(screen) => Ok(screen.scale_factor),
+            None => Err(CaptureError::ScreenNotFound(*index)),
+        },
+        CaptureTarget::Window(id) => match source.windows()?.iter().find(|w| w.id == *id) {
+            Some(window) => Ok(window.scale_factor),
+            None => Ok(1.0),
+        },
+    }
+}
+
+/// Return the position of the mouse pointer in pixels of the images captured
+/// from `target`, or `None` if the backend can not provide it
+pub fn cursor_position_in(
+    source: &dyn CaptureSource,
+    target: &CaptureTarget,
+    scale_factor: f64,
+) -> Result<Option<(i32, i32)>, CaptureError> {
+    let (x, y) = match source.cursor_position()? {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+
+    let (origin_x, origin_y) = match target {
+        CaptureTarget::Screen(ALL_SCREENS) => {
+            let (min_x, min_y, _, _) = bounds_of_screens(&source.screens()?);
+            (min_x, min_y)
+        }
+        CaptureTarget::Screen(index) => match source.screens()?.get(*index) {
+            Some(screen) => (screen.x, screen.y),
+            None => return Err(CaptureError::ScreenNotFound(*index)),
+        },
+        CaptureTarget::Window(id) => match source.windows()?.iter().find(|w| w.id == *id) {
+            Some(window) => (window.x, window.y),
+            None => return Ok(None),
+        },
+    };
+
+    return Ok(Some((
+        ((x - origin_x) as f64 * scale_factor).round() as i32,
+        ((y - origin_y) as f64 * scale_factor).round() as i32,
+    )));
+}
+
+/// This function captures `target` like `take_screenshot`,
+/// recording on the image the scale factor it has been captured with.
+/// If `include_cursor` is set the arrow sprite is drawn at the pointer position
+pub fn take_scaled_screenshot(
+    source: &dyn CaptureSource,
+    target: &CaptureTarget,
+    include_cursor: bool,
+) -> Result<CapturedImage, CaptureError> {
+    let scale_factor = scale_factor_of(source, target)?;
+    let mut image = take_screenshot(source, target)?;
+
+    if include_cursor {
+        if let Some(position) = cursor_position_in(source, target, scale_factor)? {
+            image = draw_cursor(&image, position, scale_factor);
+        }
+    }
+
+    return Ok(CapturedImage {
+        image,
+        scale_factor,
+    });
+}
+
+/// Return a copy of the image downscaled from physical to logical resolution,
+/// that is divided by `scale_factor`. The image is returned as it is if it is not scaled
+pub fn to_logical_resolution(image: &ImageBuf, scale_factor: f64) -> ImageBuf {
+    if scale_factor <= 1.0 || image.raw_pixels().is_empty() {
+        return image.clone();
+    }
+
+    let width = ((image.width() as f64 / scale_factor).round() as u32).max(1);
+    let height = ((image.height() as f64 / scale_factor).round() as u32).max(1);
+
+    return scale_to_size(image, width, height);
+}
+
+/// Return the rect `(x, y, width, height)` that contains all the given screens
+fn bounds_of_screens(screens: &Vec<ScreenInfo>) -> (i32, i32, u32, u32) {
+    if screens.is_empty() {
+        return (0, 0, 0, 0);
+    }
+
+    let min_x = screens.iter().map(|s| s.x).min().unwrap();
+    let min_y = screens.iter().map(|s| s.y).min().unwrap();
+    let max_x = screens.iter().map(|s| s.x + s.width as i32).max().unwrap();
+    let max_y = screens.iter().map(|s| s.y + s.height as i32).max().unwrap();
+
+    return (min_x, min_y, (max_x - min_x) as u32, (max_y - min_y) as u32);
+}
+
+/// Return the rect `(x, y, width, height)` of the virtual desktop,
+/// that is the union of the rects of all the screens.
+/// The top left corner can be negative if a screen is placed
+/// on the left or above the main one
+pub fn virtual_desktop_bounds(
+    source: &dyn CaptureSource,
+) -> Result<(i32, i32, u32, u32), CaptureError> {
+    Ok(bounds_of_screens(&source.screens()?))
+}
+
+/// This function works like `calculate_constrained_area` but the coordinates are expressed in the
+/// space of the given bounds `(x, y, width, height)`, for example the virtual desktop
+/// returned by `virtual_desktop_bounds`. The selection is clamped to the bounds, so it
+/// can span more monitors, and the returned `Area` is relative to the top left corner of the bounds.
+pub fn calculate_area_in_bounds(
+    (origin_x, origin_y, width, height): (i32, i32, u32, u32),
+    start_coords: (i32, i32),
+    end_coords: (i32, i32),
+    constraint: AreaConstraint,
+) -> Option<Area> {
+    return calculate_constrained_area(
+        (width, height),
+        (start_coords.0 - origin_x, start_coords.1 - origin_y),
+        (end_coords.0 - origin_x, end_coords.1 - origin_y),
+        constraint,
     );
-    return Some(image_view);
 }
 
 /// This function verifies if the drag&drop comes from left to right, form top to bottom or viceversa, then i calculates
@@ -53,6 +367,13 @@ pub fn calculate_area(
     if end_coords.0 < 0 {
         end_coords.0 = 0;
     }
+    // the screenshot area is between the current screen and a screen above it
+    if start_coords.1 < 0 {
+        start_coords.1 = 0;
+    }
+    if end_coords.1 < 0 {
+        end_coords.1 = 0;
+    }
     // the screenshot area is between the current screen and a screen on his right
     if start_coords.0 as u32 > screen_width {
         start_coords.0 = screen_width as i32;
@@ -61,7 +382,7 @@ pub fn calculate_area(
         end_coords.0 = screen_width as i32;
     }
 
-    // the screenshot area is between the current screen and a screen on his right
+    // the screenshot area is between the current screen and a screen below it
     if start_coords.1 as u32 > screen_height {
         start_coords.1 = screen_height as i32;
     }
@@ -102,23 +423,334 @@ pub fn calculate_area(
     });
 }
 
-/// This function recieve a delay expressed in u64 and,
-/// the current screen then it calls `take_screenshot`.
-pub fn take_screenshot_with_delay(time: f64, current_screen: usize) -> Option<ImageBuf> {
-    let sleep_time = Duration::new((time - 0.5) as u64, 0.0 as u32);
+/// Return a copy of the given area of the image,
+/// or `CaptureError::AreaOutOfBounds` if the area does not fit inside the image
+pub fn crop_image(image: &ImageBuf, area: &Area) -> Result<ImageBuf, CaptureError> {
+    let (offset_c, offset_r) = (area.left_corner.0 as usize, area.left_corner.1 as usize);
+    let (width, height) = (area.width as usize, area.height as usize);
+
+    if width == 0
+        || height == 0
+        || offset_c + width > image.width()
+        || offset_r + height > image.height()
+    {
+        return Err(CaptureError::AreaOutOfBounds(area.clone()));
+    }
+
+    let pixels = image.raw_pixels();
+    let mut container: Vec<u8> = Vec::with_capacity(width * height * 4);
+
+    for r in offset_r..offset_r + height {
+        let from = (r * image.width() + offset_c) * 4;
+        container.extend_from_slice(&pixels[from..from + width * 4]);
+    }
+
+    return Ok(ImageBuf::from_raw(
+        container,
+        druid::piet::ImageFormat::RgbaSeparate,
+        width,
+        height,
+    ));
+}
+
+/// This function works like `calculate_area`, but the returned area respects the given constraint.
+/// `None` is returned if the area is not valid, for example if a fixed size is bigger than the screen
+pub fn calculate_constrained_area(
+    (screen_width, screen_height): (u32, u32),
+    start_coords: (i32, i32),
+    end_coords: (i32, i32),
+    constraint: AreaConstraint,
+) -> Option<Area> {
+    match constraint {
+        AreaConstraint::Free => {
+            calculate_area((screen_width, screen_height), start_coords, end_coords)
+        }
+        AreaConstraint::FixedSize(width, height) => {
+            if width == 0 || height == 0 || width > screen_width || height > screen_height {
+                return None;
+            }
+
+            let left = (end_coords.0 - width as i32 / 2).clamp(0, (screen_width - width) as i32);
+            let top = (end_coords.1 - height as i32 / 2).clamp(0, (screen_height - height) as i32);
+
+            return Some(Area {
+                left_corner: (left as u32, top as u32),
+                width,
+                height,
+            });
+        }
+        AreaConstraint::AspectRatio(ratio_width, ratio_height) => {
+            if ratio_width == 0 || ratio_height == 0 {
+                return calculate_area((screen_width, screen_height), start_coords, end_coords);
+            }
+
+            // both the corners are moved inside the screen, so the fitted area is inside it too
+            let start_coords = (
+                start_coords.0.clamp(0, screen_width as i32),
+                start_coords.1.clamp(0, screen_height as i32),
+            );
+            let end_coords = (
+                end_coords.0.clamp(0, screen_width as i32),
+                end_coords.1.clamp(0, screen_height as i32),
+            );
+            let x_diff = (end_coords.0 - start_coords.0) as i64;
+            let y_diff = (end_coords.1 - start_coords.1) as i64;
+
+            let mut width = x_diff.abs();
+            let mut height = width * ratio_height as i64 / ratio_width as i64;
+            if height > y_diff.abs() {
+                height = y_diff.abs();
+                width = height * ratio_width as i64 / ratio_height as i64;
+            }
+
+            let end_coords = (
+                start_coords.0 + (x_diff.signum() * width) as i32,
+                start_coords.1 + (y_diff.signum() * height) as i32,
+            );
+
+            return calculate_area((screen_width, screen_height), start_coords, end_coords);
+        }
+    }
+}
+
+/// This function waits for the given delay expressed in milliseconds.
+/// The wait is splitted in short steps, so that it is precise and it stops as soon as
+/// `cancelled` is set, in this case `CaptureError::Cancelled` is returned.
+pub fn wait_with_cancel(delay_ms: u64, cancelled: &AtomicBool) -> Result<(), CaptureError> {
+    let deadline = Instant::now() + Duration::from_millis(delay_ms);
+
+    loop {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(CaptureError::Cancelled);
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(());
+        }
+
+        thread::sleep((deadline - now).min(Duration::from_millis(10)));
+    }
+}
+
+/// This function recieve a delay expressed in milliseconds and
+/// the target to capture, then it calls `take_scaled_screenshot` if the
+/// capture has not been cancelled in the meantime.
+/// It blocks the calling thread, so it has to be called from a worker thread.
+pub fn take_screenshot_with_delay(
+    source: &dyn CaptureSource,
+    delay_ms: u64,
+    target: &CaptureTarget,
+    include_cursor: bool,
+    cancelled: &AtomicBool,
+) -> Result<CapturedImage, CaptureError> {
+    wait_with_cancel(delay_ms, cancelled)?;
+
+    return take_scaled_screenshot(source, target, include_cursor);
+}
+
+/// Return the list of windows that can be captured
+pub fn list_windows(source: &dyn CaptureSource) -> Result<Vec<WindowInfo>, CaptureError> {
+    source.windows()
+}
+
+pub fn number_of_screens(source: &dyn CaptureSource) -> Result<usize, CaptureError> {
+    Ok(source.screens()?.len())
+}
+
+/// Return the list of the available screens, in the order used by the screen indexes
+pub fn list_screens(source: &dyn CaptureSource) -> Result<Vec<ScreenInfo>, CaptureError> {
+    source.screens()
+}
 
-    thread::sleep(sleep_time);
+/// Return the primary screen, or the first one if no screen is marked as primary
+pub fn primary_screen(source: &dyn CaptureSource) -> Result<ScreenInfo, CaptureError> {
+    let screens = source.screens()?;
 
-    return take_screenshot(current_screen);
+    match screens.iter().find(|s| s.is_primary).or(screens.first()) {
+        Some(screen) => Ok(screen.clone()),
+        None => Err(CaptureError::NoDisplay(format!("no screen found"))),
+    }
 }
 
-pub fn number_of_screens() -> usize {
-    Screen::all().unwrap().len()
+/// Return the logical size of the primary screen
+pub fn screen_size(source: &dyn CaptureSource) -> Result<Size, CaptureError> {
+    let screen = primary_screen(source)?;
+
+    return Ok(Size::new(screen.width as f64, screen.height as f64));
 }
 
-/// Return the size of main screen
-pub fn screen_size() -> Size {
-    let screen = Screen::all().unwrap()[0].display_info;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: (u32, u32) = (100, 80);
+
+    fn area(left_corner: (u32, u32), width: u32, height: u32) -> Option<Area> {
+        Some(Area {
+            left_corner,
+            width,
+            height,
+        })
+    }
+
+    #[test]
+    fn free_area_follows_the_drag() {
+        let constraint = AreaConstraint::Free;
+
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (10, 10), (40, 30), constraint),
+            area((10, 10), 30, 20)
+        );
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (40, 30), (10, 10), constraint),
+            area((10, 10), 30, 20)
+        );
+    }
+
+    #[test]
+    fn fixed_size_area_is_centred_on_the_end_of_the_drag() {
+        let constraint = AreaConstraint::FixedSize(20, 10);
+
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (0, 0), (50, 40), constraint),
+            area((40, 35), 20, 10)
+        );
+        // the frame is kept inside the screen
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (0, 0), (99, 79), constraint),
+            area((80, 70), 20, 10)
+        );
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (0, 0), (-5, -5), constraint),
+            area((0, 0), 20, 10)
+        );
+    }
+
+    #[test]
+    fn fixed_size_bigger_than_the_screen_is_not_valid() {
+        let constraint = AreaConstraint::FixedSize(200, 10);
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (0, 0), (50, 40), constraint),
+            None
+        );
+
+        let constraint = AreaConstraint::FixedSize(0, 10);
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (0, 0), (50, 40), constraint),
+            None
+        );
+    }
+
+    #[test]
+    fn aspect_ratio_area_fits_the_drag() {
+        let constraint = AreaConstraint::AspectRatio(16, 9);
+
+        // limited by the width
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (0, 0), (64, 80), constraint),
+            area((0, 0), 64, 36)
+        );
+        // limited by the height
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (0, 0), (90, 18), constraint),
+            area((0, 0), 32, 18)
+        );
+        // dragged from the bottom right to the top left
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (64, 36), (0, 0), constraint),
+            area((0, 0), 64, 36)
+        );
+        // dragged outside the screen
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (90, 70), (200, 200), constraint),
+            area((90, 70), 10, 5)
+        );
+    }
 
-    return Size::new(screen.width as f64, screen.height as f64);
+    #[test]
+    fn aspect_ratio_with_a_zero_term_is_free() {
+        let constraint = AreaConstraint::AspectRatio(0, 9);
+
+        assert_eq!(
+            calculate_constrained_area(SCREEN, (10, 10), (40, 30), constraint),
+            area((10, 10), 30, 20)
+        );
+    }
+    fn screen(x: i32, y: i32, width: u32, height: u32, scale_factor: f64) -> ScreenInfo {
+        ScreenInfo {
+            id: 0,
+            x,
+            y,
+            width,
+            height,
+            scale_factor,
+            rotation: 0.0,
+            is_primary: x == 0 && y == 0,
+        }
+    }
+
+    #[test]
+    fn selection_crosses_a_monitor_with_negative_origin() {
+        // a monitor on the left of the main one
+        let bounds = bounds_of_screens(&vec![
+            screen(-1920, 0, 1920, 1080, 1.0),
+            screen(0, 0, 2560, 1440, 1.0),
+        ]);
+        assert_eq!(bounds, (-1920, 0, 4480, 1440));
+
+        let constraint = AreaConstraint::Free;
+        assert_eq!(
+            calculate_area_in_bounds(bounds, (-100, 500), (300, 1000), constraint),
+            area((1820, 500), 400, 500)
+        );
+        // the selection is clamped to the virtual desktop
+        assert_eq!(
+            calculate_area_in_bounds(bounds, (-3000, -10), (300, 2000), constraint),
+            area((0, 0), 2220, 1440)
+        );
+    }
+
+    /// Two screens side by side, the left one at 1x and the right one at 2x,
+    /// every capture is filled with a different colour
+    struct MixedDpiSource;
+
+    impl CaptureSource for MixedDpiSource {
+        fn screens(&self) -> Result<Vec<ScreenInfo>, CaptureError> {
+            Ok(vec![screen(0, 0, 2, 2, 1.0), screen(2, 0, 2, 2, 2.0)])
+        }
+
+        fn capture(&self, index: usize) -> Result<ImageBuf, CaptureError> {
+            let (size, color) = match index {
+                0 => (2, [255, 0, 0, 255]),
+                _ => (4, [0, 0, 255, 255]),
+            };
+
+            Ok(ImageBuf::from_raw(
+                color.repeat(size * size),
+                druid::piet::ImageFormat::RgbaSeparate,
+                size,
+                size,
+            ))
+        }
+    }
+
+    #[test]
+    fn screens_with_lower_scale_factor_fill_their_place() {
+        let image = take_screenshot(&MixedDpiSource, &CaptureTarget::Screen(ALL_SCREENS)).unwrap();
+        assert_eq!((image.width(), image.height()), (8, 4));
+
+        let pixels = image.raw_pixels();
+        for y in 0..4 {
+            for x in 0..8 {
+                let i = (y * 8 + x) * 4;
+                let expected = if x < 4 {
+                    [255, 0, 0, 255]
+                } else {
+                    [0, 0, 255, 255]
+                };
+                assert_eq!(&pixels[i..i + 4], &expected, "pixel ({}, {})", x, y);
+            }
+        }
+    }
 }
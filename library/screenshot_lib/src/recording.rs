@@ -0,0 +1,375 @@
+pub mod recording {
+    use crate::{
+        crop_image, source::source::CaptureSource, take_scaled_screenshot, wait_with_cancel, Area,
+        CaptureError, CaptureTarget,
+    };
+    use druid::Data;
+    use image::{
+        codecs::gif::{GifEncoder, Repeat},
+        Delay, Frame, RgbaImage,
+    };
+    use std::{
+        fs::{self, File},
+        io::{BufReader, BufWriter, Read, Write},
+        path::{Path, PathBuf},
+        sync::atomic::AtomicBool,
+        time::{Duration, Instant},
+    };
+
+    /// Formats available for the animated file of a recording
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Data)]
+    pub enum RecordingFormat {
+        Gif,
+        Apng,
+    }
+
+    impl RecordingFormat {
+        /// Return the extension of the file for the format
+        pub fn extension(&self) -> &'static str {
+            match self {
+                RecordingFormat::Gif => "gif",
+                RecordingFormat::Apng => "png",
+            }
+        }
+    }
+
+    /// Settings of a recording
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct RecordingSettings {
+        /// Number of frames captured every second
+        pub fps: u32,
+        /// Maximum duration of the recording, in milliseconds
+        pub max_duration_ms: u64,
+        /// Region of the screen to record, `None` means the whole screen
+        pub area: Option<Area>,
+        /// Draw the mouse pointer on the frames
+        pub include_cursor: bool,
+    }
+
+    /// A frame of a recording in RGBA format,
+    /// with the time it has to be shown for
+    #[derive(Clone, Debug)]
+    pub struct RecordedFrame {
+        pub width: u32,
+        pub height: u32,
+        pub pixels: Vec<u8>,
+        pub duration_ms: u64,
+    }
+
+    /// This function captures `target` (or only `settings.area` of it) `settings.fps` times
+    /// per second, until `settings.max_duration_ms` is reached or `cancelled` is set.
+    /// A frame equal to the previous one is not stored, instead the duration of the
+    /// previous frame is extended, so static periods don't make the recording bigger.
+    /// Only the last frame is kept in memory: it is written in `encoder` as soon as
+    /// a different frame is captured, so its duration is known.
+    /// After every capture `progress` is called with the number of frames captured.
+    /// It blocks the calling thread, so it has to be called from a worker thread.
+    pub fn record_frames(
+        source: &dyn CaptureSource,
+        target: &CaptureTarget,
+        settings: &RecordingSettings,
+        encoder: &mut RecordingEncoder,
+        cancelled: &AtomicBool,
+        mut progress: impl FnMut(u32),
+    ) -> Result<(), CaptureError> {
+        let frame_ms = 1000 / settings.fps.max(1) as u64;
+        let start = Instant::now();
+        let mut last: Option<RecordedFrame> = None;
+        let mut captured = 0;
+
+        while start.elapsed() < Duration::from_millis(settings.max_duration_ms) {
+            let mut image = take_scaled_screenshot(source, target, settings.include_cursor)?.image;
+            if let Some(area) = &settings.area {
+                image = crop_image(&image, area)?;
+            }
+            captured += 1;
+
+            let duplicated = match last.as_mut() {
+                Some(last) if last.pixels.as_slice() == image.raw_pixels() => {
+                    last.duration_ms += frame_ms;
+                    true
+                }
+                _ => false,
+            };
+
+            if !duplicated {
+                let frame = RecordedFrame {
+                    width: image.width() as u32,
+                    height: image.height() as u32,
+                    pixels: image.raw_pixels().to_vec(),
+                    duration_ms: frame_ms,
+                };
+
+                if let Some(previous) = last.replace(frame) {
+                    encoder.write_frame(previous)?;
+                }
+            }
+
+            progress(captured);
+
+            // the deadline of each frame is computed from the start, so that the delays don't accumulate
+            let deadline = Duration::from_millis(frame_ms * captured as u64);
+            let remaining = deadline.saturating_sub(start.elapsed());
+            if wait_with_cancel(remaining.as_millis() as u64, cancelled).is_err() {
+                break;
+            }
+        }
+
+        if let Some(last) = last {
+            encoder.write_frame(last)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Record `target` like `record_frames` in an animated file of the given format.
+    /// If the recording fails no file is left in `path`
+    pub fn record_to_file(
+        source: &dyn CaptureSource,
+        target: &CaptureTarget,
+        settings: &RecordingSettings,
+        format: RecordingFormat,
+        path: &Path,
+        cancelled: &AtomicBool,
+        progress: impl FnMut(u32),
+    ) -> Result<(), CaptureError> {
+        let mut encoder = RecordingEncoder::new(format, path)?;
+
+        match record_frames(source, target, settings, &mut encoder, cancelled, progress) {
+            Ok(_) => encoder.finish(),
+            Err(err) => {
+                encoder.discard();
+                Err(err)
+            }
+        }
+    }
+
+    /// Writer of the animated file of a recording, that receives the frames one at a time.
+    /// GIF frames are encoded straight away, while APNG needs the number of frames
+    /// before the first one, so its frames are spooled to a temporary file until `finish`
+    pub struct RecordingEncoder {
+        path: PathBuf,
+        frames: u32,
+        kind: EncoderKind,
+    }
+
+    enum EncoderKind {
+        Gif(GifEncoder<BufWriter<File>>),
+        Apng {
+            file: File,
+            spool: BufWriter<File>,
+            spool_path: PathBuf,
+            size: Option<(u32, u32)>,
+        },
+    }
+
+    impl RecordingEncoder {
+        /// Create the file of the recording in `path`
+        pub fn new(format: RecordingFormat, path: &Path) -> Result<Self, CaptureError> {
+            let file = File::create(path)
+                .map_err(|e| CaptureError::SaveFailed(format!("{}: {}", path.display(), e)))?;
+
+            let kind = match format {
+                RecordingFormat::Gif => {
+                    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+                    encoder
+                        .set_repeat(Repeat::Infinite)
+                        .map_err(|e| CaptureError::SaveFailed(e.to_string()))?;
+
+                    EncoderKind::Gif(encoder)
+                }
+                RecordingFormat::Apng => {
+                    let mut spool_name = path.file_name().unwrap_or_default().to_os_string();
+                    spool_name.push(".frames");
+                    let spool_path = std::env::temp_dir().join(spool_name);
+                    let spool = File::create(&spool_path).map_err(|e| {
+                        CaptureError::SaveFailed(format!("{}: {}", spool_path.display(), e))
+                    })?;
+
+                    EncoderKind::Apng {
+                        file,
+                        spool: BufWriter::new(spool),
+                        spool_path,
+                        size: None,
+                    }
+                }
+            };
+
+            return Ok(RecordingEncoder {
+                path: path.to_path_buf(),
+                frames: 0,
+                kind,
+            });
+        }
+
+        /// Add a frame at the end of the recording
+        pub fn write_frame(&mut self, frame: RecordedFrame) -> Result<(), CaptureError> {
+            let result = match &mut self.kind {
+                EncoderKind::Gif(encoder) => write_gif_frame(encoder, frame),
+                EncoderKind::Apng { spool, size, .. } => spool_apng_frame(spool, size, frame),
+            };
+            self.frames += 1;
+
+            result.map_err(|e| CaptureError::SaveFailed(format!("{}: {}", self.path.display(), e)))
+        }
+
+        /// Complete the file of the recording, it fails if no frame has been written
+        pub fn finish(self) -> Result<(), CaptureError> {
+            let path = self.path.clone();
+
+            if self.frames == 0 {
+                self.discard();
+                return Err(CaptureError::SaveFailed(format!("no frame recorded")));
+            }
+
+            let result = match self.kind {
+                EncoderKind::Gif(encoder) => {
+                    // the frames are flushed when the encoder is dropped
+                    drop(encoder);
+                    Ok(())
+                }
+                EncoderKind::Apng {
+                    file,
+                    spool,
+                    spool_path,
+                    size,
+                } => {
+                    let result = encode_apng(file, spool, &spool_path, size, self.frames);
+                    let _ = fs::remove_file(&spool_path);
+                    result
+                }
+            };
+
+            if result.is_err() {
+                let _ = fs::remove_file(&path);
+            }
+
+            result.map_err(|e| CaptureError::SaveFailed(format!("{}: {}", path.display(), e)))
+        }
+
+        /// Stop the recording and remove its files
+        pub fn discard(self) {
+            if let EncoderKind::Apng { spool_path, .. } = &self.kind {
+                let _ = fs::remove_file(spool_path);
+            }
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn write_gif_frame(
+        encoder: &mut GifEncoder<BufWriter<File>>,
+        frame: RecordedFrame,
+    ) -> Result<(), String> {
+        let delay = Delay::from_numer_denom_ms(frame.duration_ms as u32, 1);
+        let buffer = match RgbaImage::from_raw(frame.width, frame.height, frame.pixels) {
+            Some(buffer) => buffer,
+            None => return Err(format!("invalid frame buffer")),
+        };
+
+        encoder
+            .encode_frame(Frame::from_parts(buffer, 0, 0, delay))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Append the duration and the pixels of the frame to the spool file of an APNG
+    fn spool_apng_frame(
+        spool: &mut BufWriter<File>,
+        size: &mut Option<(u32, u32)>,
+        frame: RecordedFrame,
+    ) -> Result<(), String> {
+        match size {
+            Some(size) if *size != (frame.width, frame.height) => {
+                return Err(format!("all the frames must have the same size"));
+            }
+            Some(_) => {}
+            None => *size = Some((frame.width, frame.height)),
+        }
+
+        spool
+            .write_all(&frame.duration_ms.to_le_bytes())
+            .map_err(|e| e.to_string())?;
+        spool.write_all(&frame.pixels).map_err(|e| e.to_string())
+    }
+
+    /// Encode the frames spooled by `spool_apng_frame` in `file`, reading them one at a time
+    fn encode_apng(
+        file: File,
+        spool: BufWriter<File>,
+        spool_path: &Path,
+        size: Option<(u32, u32)>,
+        frames: u32,
+    ) -> Result<(), String> {
+        let (width, height) = size.ok_or(format!("no frame recorded"))?;
+        spool
+            .into_inner()
+            .map_err(|e| e.to_string())?
+            .sync_all()
+            .map_err(|e| e.to_string())?;
+
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(frames, 0).map_err(|e| e.to_string())?;
+
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        let mut reader = BufReader::new(File::open(spool_path).map_err(|e| e.to_string())?);
+        let mut duration = [0u8; 8];
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+
+        for _ in 0..frames {
+            reader
+                .read_exact(&mut duration)
+                .map_err(|e| e.to_string())?;
+            reader.read_exact(&mut pixels).map_err(|e| e.to_string())?;
+
+            // the delay is a fraction of seconds with 16 bit terms, so it is expressed in centiseconds
+            let delay_cs = (u64::from_le_bytes(duration) / 10).min(u16::MAX as u64) as u16;
+            writer
+                .set_frame_delay(delay_cs, 100)
+                .map_err(|e| e.to_string())?;
+            writer
+                .write_image_data(&pixels)
+                .map_err(|e| e.to_string())?;
+        }
+
+        writer.finish().map_err(|e| e.to_string())
+    }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::source::source::{screens_dir, FileSource};
+
+        #[test]
+        fn static_screen_is_recorded_as_one_frame() {
+            let dir = screens_dir("recording", &[(4, 3, [0, 255, 0, 255])]);
+
+            let settings = RecordingSettings {
+                fps: 20,
+                max_duration_ms: 200,
+                area: None,
+                include_cursor: false,
+            };
+            // the file is not placed in `dir`, that has to contain only the screens
+            let path = std::env::temp_dir().join(format!("recording_{}.png", std::process::id()));
+            record_to_file(
+                &FileSource::new(&dir),
+                &CaptureTarget::Screen(0),
+                &settings,
+                RecordingFormat::Apng,
+                &path,
+                &AtomicBool::new(false),
+                |_| {},
+            )
+            .unwrap();
+
+            let decoder = png::Decoder::new(File::open(&path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            let animation = reader.info().animation_control.unwrap();
+            assert_eq!(animation.num_frames, 1);
+            assert_eq!((reader.info().width, reader.info().height), (4, 3));
+
+            fs::remove_file(path).unwrap();
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,339 @@
+pub mod source {
+    use crate::CaptureError;
+    use druid::{piet::ImageFormat, Data, ImageBuf};
+    use mouse_position::mouse_position::Mouse;
+    use screenshots::Screen;
+    use std::{
+        fs::read_dir,
+        path::{Path, PathBuf},
+    };
+    use xcap::Window;
+
+    /// Position and logical size of a screen inside the virtual desktop,
+    /// the physical size in pixels is the logical one multiplied by `scale_factor`
+    #[derive(Clone, Debug, PartialEq, Data)]
+    pub struct ScreenInfo {
+        /// Identifier of the screen given by the OS
+        pub id: u32,
+        pub x: i32,
+        pub y: i32,
+        pub width: u32,
+        pub height: u32,
+        pub scale_factor: f64,
+        /// Rotation of the screen in degrees
+        pub rotation: f64,
+        pub is_primary: bool,
+    }
+
+    /// Title and geometry of an application window that can be captured,
+    /// with the scale factor of the monitor it is placed on
+    #[derive(Clone, Debug, PartialEq, Data)]
+    pub struct WindowInfo {
+        pub id: u32,
+        pub title: String,
+        pub app_name: String,
+        pub x: i32,
+        pub y: i32,
+        pub width: u32,
+        pub height: u32,
+        pub scale_factor: f64,
+    }
+
+    /// This trait abstracts the backend used to list and capture the screens,
+    /// so that the capture flow can be driven by a real display or by a fake one
+    pub trait CaptureSource: Send + Sync {
+        /// Return the geometry of every available screen
+        fn screens(&self) -> Result<Vec<ScreenInfo>, CaptureError>;
+        /// Capture the whole screen with the given index in a RGBA `ImageBuf`,
+        /// the image has the physical resolution of the screen
+        fn capture(&self, index: usize) -> Result<ImageBuf, CaptureError>;
+        /// Return the list of windows that can be captured.
+        /// The default implementation has no window
+        fn windows(&self) -> Result<Vec<WindowInfo>, CaptureError> {
+            Ok(vec![])
+        }
+        /// Capture the window with the given id in a RGBA `ImageBuf`
+        fn capture_window(&self, id: u32) -> Result<ImageBuf, CaptureError> {
+            Err(CaptureError::WindowNotFound(id))
+        }
+        /// Return the position of the mouse pointer inside the virtual desktop,
+        /// `None` if it is not available. The default implementation has no pointer
+        fn cursor_position(&self) -> Result<Option<(i32, i32)>, CaptureError> {
+            Ok(None)
+        }
+    }
+
+    /// Capture backend of the real display, it needs three crates:
+    /// - `screenshots` lists and captures the screens, its geometry is in logical
+    ///   pixels on every platform as `ScreenInfo` expects, while the monitors of
+    ///   `xcap` 0.0.14 have the physical size on Windows;
+    /// - `xcap` lists and captures the application windows, that `screenshots` 0.8 lacks;
+    /// - `mouse_position` gives the pointer position, that neither of them exposes.
+    #[derive(Clone, Debug, Default)]
+    pub struct ScreenshotsSource;
+
+    impl ScreenshotsSource {
+        pub fn new() -> Self {
+            ScreenshotsSource
+        }
+
+        /// Return the list of available screens, or an error if there is no display
+        fn all_screens(&self) -> Result<Vec<Screen>, CaptureError> {
+            let screens = Screen::all().map_err(|e| CaptureError::NoDisplay(e.to_string()))?;
+
+            if screens.is_empty() {
+                return Err(CaptureError::NoDisplay(format!("no screen found")));
+            }
+
+            return Ok(screens);
+        }
+    }
+
+    impl CaptureSource for ScreenshotsSource {
+        fn screens(&self) -> Result<Vec<ScreenInfo>, CaptureError> {
+            let screens = self.all_screens()?;
+
+            return Ok(screens
+                .iter()
+                .map(|s| ScreenInfo {
+                    id: s.display_info.id,
+                    x: s.display_info.x,
+                    y: s.display_info.y,
+                    width: s.display_info.width,
+                    height: s.display_info.height,
+                    scale_factor: s.display_info.scale_factor as f64,
+                    rotation: s.display_info.rotation as f64,
+                    is_primary: s.display_info.is_primary,
+                })
+                .collect());
+        }
+
+        fn capture(&self, index: usize) -> Result<ImageBuf, CaptureError> {
+            let screens = self.all_screens()?;
+            let screen = match screens.get(index) {
+                Some(screen) => *screen,
+                None => return Err(CaptureError::ScreenNotFound(index)),
+            };
+
+            // on scaled displays the captured image has the physical size of the screen,
+            // that differs from the logical size in `display_info`
+            let image = screen
+                .capture()
+                .map_err(|e| CaptureError::from_capture(e.to_string()))?;
+            let (width, height) = (image.width(), image.height());
+
+            return Ok(ImageBuf::from_raw(
+                image.into_raw(),
+                ImageFormat::RgbaSeparate,
+                width as usize,
+                height as usize,
+            ));
+        }
+
+        fn windows(&self) -> Result<Vec<WindowInfo>, CaptureError> {
+            let windows = Window::all().map_err(|e| CaptureError::NoDisplay(e.to_string()))?;
+
+            return Ok(windows
+                .iter()
+                .filter(|w| !w.is_minimized() && w.width() > 0 && w.height() > 0)
+                .map(|w| WindowInfo {
+                    id: w.id(),
+                    title: w.title().to_string(),
+                    app_name: w.app_name().to_string(),
+                    x: w.x(),
+                    y: w.y(),
+                    width: w.width(),
+                    height: w.height(),
+                    scale_factor: w.current_monitor().scale_factor() as f64,
+                })
+                .collect());
+        }
+
+        fn capture_window(&self, id: u32) -> Result<ImageBuf, CaptureError> {
+            let windows = Window::all().map_err(|e| CaptureError::NoDisplay(e.to_string()))?;
+            let window = match windows.iter().find(|w| w.id() == id) {
+                Some(window) => window,
+                None => return Err(CaptureError::WindowNotFound(id)),
+            };
+
+            let image = window
+                .capture_image()
+                .map_err(|e| CaptureError::from_capture(e.to_string()))?;
+            let (width, height) = (image.width(), image.height());
+
+            return Ok(ImageBuf::from_raw(
+                image.into_raw(),
+                ImageFormat::RgbaSeparate,
+                width as usize,
+                height as usize,
+            ));
+        }
+
+        fn cursor_position(&self) -> Result<Option<(i32, i32)>, CaptureError> {
+            match Mouse::get_mouse_position() {
+                Mouse::Position { x, y } => Ok(Some((x, y))),
+                Mouse::Error => Ok(None),
+            }
+        }
+    }
+
+    /// Capture backend that serves the PNG files of a directory as fake monitors.
+    /// The files are sorted by name and every file is a screen, placed on the
+    /// right of the previous one. The first file is the primary screen. Every capture reads the file again, so the
+    /// returned pixels are always the ones stored on disk.
+    #[derive(Clone, Debug)]
+    pub struct FileSource {
+        dir: PathBuf,
+    }
+
+    impl FileSource {
+        pub fn new(dir: impl AsRef<Path>) -> Self {
+            FileSource {
+                dir: dir.as_ref().to_path_buf(),
+            }
+        }
+
+        /// Return the sorted list of PNG files in the directory
+        fn files(&self) -> Result<Vec<PathBuf>, CaptureError> {
+            let entries =
+                read_dir(&self.dir).map_err(|e| CaptureError::NoDisplay(e.to_string()))?;
+
+            let mut files: Vec<PathBuf> = entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| match p.extension() {
+                    Some(ext) => ext.eq_ignore_ascii_case("png"),
+                    None => false,
+                })
+                .collect();
+            files.sort();
+
+            if files.is_empty() {
+                return Err(CaptureError::NoDisplay(format!(
+                    "no PNG file found in {}",
+                    self.dir.display()
+                )));
+            }
+
+            return Ok(files);
+        }
+    }
+
+    impl CaptureSource for FileSource {
+        fn screens(&self) -> Result<Vec<ScreenInfo>, CaptureError> {
+            let mut screens = vec![];
+            let mut x = 0;
+
+            for (index, file) in self.files()?.iter().enumerate() {
+                let (width, height) = image::image_dimensions(file)
+                    .map_err(|e| CaptureError::NoDisplay(e.to_string()))?;
+
+                screens.push(ScreenInfo {
+                    id: index as u32,
+                    x,
+                    y: 0,
+                    width,
+                    height,
+                    scale_factor: 1.0,
+                    rotation: 0.0,
+                    is_primary: index == 0,
+                });
+                x += width as i32;
+            }
+
+            return Ok(screens);
+        }
+
+        fn capture(&self, index: usize) -> Result<ImageBuf, CaptureError> {
+            let files = self.files()?;
+            let file = match files.get(index) {
+                Some(file) => file,
+                None => return Err(CaptureError::ScreenNotFound(index)),
+            };
+
+            let image = image::open(file)
+                .map_err(|e| CaptureError::CaptureFailed(e.to_string()))?
+                .to_rgba8();
+            let (width, height) = (image.width(), image.height());
+
+            return Ok(ImageBuf::from_raw(
+                image.into_raw(),
+                ImageFormat::RgbaSeparate,
+                width as usize,
+                height as usize,
+            ));
+        }
+    }
+
+    /// Create a new temporary directory with a PNG of the given size and colour
+    /// for every screen, that `FileSource` serves as a fake display.
+    /// The name has to be unique among the tests, as they run in parallel
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn screens_dir(name: &str, screens: &[(u32, u32, [u8; 4])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("screens_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("Unable to create the screens dir");
+
+        for (index, (width, height, color)) in screens.iter().enumerate() {
+            image::RgbaImage::from_pixel(*width, *height, image::Rgba(*color))
+                .save(dir.join(format!("screen_{}.png", index)))
+                .expect("Unable to write the screen");
+        }
+
+        return dir;
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::fs;
+
+        const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+        #[test]
+        fn screens_are_placed_side_by_side() {
+            let dir = screens_dir("side_by_side", &[(4, 3, BLACK), (2, 5, BLACK)]);
+            let screens = FileSource::new(&dir).screens().unwrap();
+
+            assert_eq!(screens.len(), 2);
+            assert_eq!(
+                (screens[0].x, screens[0].width, screens[0].height),
+                (0, 4, 3)
+            );
+            assert_eq!(
+                (screens[1].x, screens[1].width, screens[1].height),
+                (4, 2, 5)
+            );
+            assert!(screens[0].is_primary && !screens[1].is_primary);
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn capture_reads_the_file_of_the_screen() {
+            let dir = screens_dir("capture", &[(4, 3, BLACK), (2, 5, BLACK)]);
+            let source = FileSource::new(&dir);
+
+            let image = source.capture(1).unwrap();
+            assert_eq!((image.width(), image.height()), (2, 5));
+            assert_eq!(
+                source.capture(2).err(),
+                Some(CaptureError::ScreenNotFound(2))
+            );
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn empty_directory_has_no_display() {
+            let dir = screens_dir("empty", &[]);
+
+            assert!(matches!(
+                FileSource::new(&dir).screens(),
+                Err(CaptureError::NoDisplay(_))
+            ));
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use directories::UserDirs;
-use druid::{im::Vector, keyboard_types::Key, Data, HotKey, SysMods};
+use druid::{im::Vector, keyboard_types::Key, Data, HotKey, Lens, SysMods};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::BTreeMap,
@@ -8,6 +8,7 @@ use std::{
     path::PathBuf,
     str::FromStr,
 };
+use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
 
@@ -19,6 +20,15 @@ const CONF_SHORTCUT_FILE_NAME: &str = "shortcut_conf.toml";
 const CONF_SAVEPATH_FILE_PATH: &str = "./conf/save_path_conf.toml";
 const CONF_SAVEPATH_FILE_NAME: &str = "save_path_conf.toml";
 
+const CONF_CAPTURE_FILE_PATH: &str = "./conf/capture_conf.toml";
+const CONF_CAPTURE_FILE_NAME: &str = "capture_conf.toml";
+
+const CONF_COLOR_FILE_PATH: &str = "./conf/color_conf.toml";
+const CONF_COLOR_FILE_NAME: &str = "color_conf.toml";
+
+/// Maximum number of recently used colours kept in the config file
+pub const RECENT_COLORS_LIMIT: usize = 8;
+
 /// This trait is used for conversion of
 /// `SysMods` type to `String` and vice versa.
 /// This trait hold in consideration the different
@@ -208,6 +218,12 @@ impl ToFromCode for SysMods {
 /// - NewScreenshot
 /// - Save
 /// - SaveAs
+/// - CancelScreenshot
+/// - CaptureLastRegion
+/// - OpenImage
+/// - PasteImage
+/// - Undo
+/// - Redo
 #[derive(
     Debug, Data, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, EnumIter, Deserialize, Serialize,
 )]
@@ -215,6 +231,12 @@ pub enum Action {
     NewScreenshot,
     Save,
     SaveAs,
+    CancelScreenshot,
+    CaptureLastRegion,
+    OpenImage,
+    PasteImage,
+    Undo,
+    Redo,
 }
 
 impl Action {
@@ -224,11 +246,23 @@ impl Action {
     /// - Action::NewScreenshot => "New screenshot"
     /// - Action::Save => "Save"
     /// - Action::SaveAs => "Save as"
+    /// - Action::CancelScreenshot => "Cancel screenshot"
+    /// - Action::CaptureLastRegion => "Capture last region"
+    /// - Action::OpenImage => "Open image"
+    /// - Action::PasteImage => "Paste from clipboard"
+    /// - Action::Undo => "Undo"
+    /// - Action::Redo => "Redo"
     pub fn to_string(&self) -> String {
         match self {
             Action::NewScreenshot => String::from_str("New screenshot").unwrap(),
             Action::Save => String::from_str("Save").unwrap(),
             Action::SaveAs => String::from_str("Save as").unwrap(),
+            Action::CancelScreenshot => String::from_str("Cancel screenshot").unwrap(),
+            Action::CaptureLastRegion => String::from_str("Capture last region").unwrap(),
+            Action::OpenImage => String::from_str("Open image").unwrap(),
+            Action::PasteImage => String::from_str("Paste from clipboard").unwrap(),
+            Action::Undo => String::from_str("Undo").unwrap(),
+            Action::Redo => String::from_str("Redo").unwrap(),
         }
     }
 
@@ -238,11 +272,23 @@ impl Action {
     /// - "New screenshot" => Action::NewScreenshot
     /// - "Save" => Action::Save
     /// - "Save as" => Action::SaveAs
+    /// - "Cancel screenshot" => Action::CancelScreenshot
+    /// - "Capture last region" => Action::CaptureLastRegion
+    /// - "Open image" => Action::OpenImage
+    /// - "Paste from clipboard" => Action::PasteImage
+    /// - "Undo" => Action::Undo
+    /// - "Redo" => Action::Redo
     pub fn from_string(action: String) -> Self {
         match action.as_str() {
             "New screenshot" => Action::NewScreenshot,
             "Save" => Action::Save,
             "Save as" => Action::SaveAs,
+            "Cancel screenshot" => Action::CancelScreenshot,
+            "Capture last region" => Action::CaptureLastRegion,
+            "Open image" => Action::OpenImage,
+            "Paste from clipboard" => Action::PasteImage,
+            "Undo" => Action::Undo,
+            "Redo" => Action::Redo,
             _ => panic!("Could not translate string to enum Action!"),
         }
     }
@@ -354,14 +400,18 @@ impl Shortcuts {
     /// - Action::NewScreenshot => SysMods::Cmd + 'n'
     /// - Action::Save => SysMods::Cmd + 's'
     /// - Action::SaveAs => SysMods::CmdShift + 's'
+    /// - Action::CancelScreenshot => SysMods::Cmd + 'k'
+    /// - Action::CaptureLastRegion => SysMods::CmdShift + 'r'
+    /// - Action::OpenImage => SysMods::Cmd + 'o'
+    /// - Action::PasteImage => SysMods::CmdShift + 'v'
+    /// - Action::Undo => SysMods::Cmd + 'z'
+    /// - Action::Redo => SysMods::CmdShift + 'z'
     fn create_toml() {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(CONF_SHORTCUT_FILE_PATH)
-            .expect("Unable to open shortcut_conf file");
+        Shortcuts::default_shortcuts().write_toml();
+    }
 
+    /// Return the default key combination of every action
+    fn default_shortcuts() -> Self {
         let mut new_shortcuts = Shortcuts::default();
 
         new_shortcuts
@@ -373,9 +423,38 @@ impl Shortcuts {
         new_shortcuts
             .shortcuts
             .insert(Action::SaveAs, Shortcut::new(SysMods::CmdShift, 's'));
+        new_shortcuts
+            .shortcuts
+            .insert(Action::CancelScreenshot, Shortcut::new(SysMods::Cmd, 'k'));
+        new_shortcuts
+            .shortcuts
+            .insert(Action::CaptureLastRegion, Shortcut::new(SysMods::CmdShift, 'r'));
+        new_shortcuts
+            .shortcuts
+            .insert(Action::OpenImage, Shortcut::new(SysMods::Cmd, 'o'));
+        new_shortcuts
+            .shortcuts
+            .insert(Action::PasteImage, Shortcut::new(SysMods::CmdShift, 'v'));
+        new_shortcuts
+            .shortcuts
+            .insert(Action::Undo, Shortcut::new(SysMods::Cmd, 'z'));
+        new_shortcuts
+            .shortcuts
+            .insert(Action::Redo, Shortcut::new(SysMods::CmdShift, 'z'));
 
-        let toml_string =
-            toml::to_string(&new_shortcuts).expect("Unable to encode data to toml format");
+        return new_shortcuts;
+    }
+
+    /// Write the shortcuts in the config file, replacing its content
+    fn write_toml(&self) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(CONF_SHORTCUT_FILE_PATH)
+            .expect("Unable to open shortcut_conf file");
+
+        let toml_string = toml::to_string(self).expect("Unable to encode data to toml format");
 
         file.write(toml_string.as_bytes())
             .expect("Could not write to shortcut_conf file");
@@ -389,7 +468,7 @@ impl Shortcuts {
         let contents =
             fs::read_to_string(CONF_SHORTCUT_FILE_PATH).expect("Could not read shortcut_conf file");
 
-        let new_shortcuts: Shortcuts =
+        let mut new_shortcuts: Shortcuts =
             toml::from_str(&contents).expect("Unable to decode data from toml");
 
         let mut error = false;
@@ -410,6 +489,30 @@ impl Shortcuts {
             return Shortcuts::new();
         }
 
+        // a config file written by an older version can miss the shortcuts of new actions:
+        // they get their default combination, unless it is already used by another action
+        let defaults = Shortcuts::default_shortcuts();
+        let missing: Vec<Action> = Action::iter()
+            .filter(|a| !new_shortcuts.shortcuts.contains_key(a))
+            .collect();
+
+        if missing.is_empty() {
+            return new_shortcuts;
+        }
+
+        for action in missing {
+            let shortcut = defaults.shortcuts[&action].clone();
+
+            if new_shortcuts.shortcuts.values().any(|s| *s == shortcut) {
+                Shortcuts::create_toml();
+                return Shortcuts::new();
+            }
+
+            new_shortcuts.shortcuts.insert(action, shortcut);
+        }
+
+        new_shortcuts.write_toml();
+
         return new_shortcuts;
     }
 
@@ -509,19 +612,7 @@ impl Shortcuts {
             Shortcut::new(SysMods::from_code(new_value.0).unwrap(), new_value.1),
         );
 
-        let toml_string = toml::to_string(&self).expect("Unable to encode data to toml format");
-
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(CONF_SHORTCUT_FILE_PATH)
-            .expect("Unable to open shortcut_conf file");
-
-        file.write(toml_string.as_bytes())
-            .expect("Could not write to shortcut_conf file");
-
-        file.flush().expect("Could not write to shortcut_conf file");
+        self.write_toml();
 
         return Ok(());
     }
@@ -670,3 +761,283 @@ impl SavePath {
         return String::from_str(self.save_path.clone().to_str().unwrap()).unwrap();
     }
 }
+
+/// This data type is used to serialize and deserialize
+/// data to/from file to save user preferences about
+/// how the screenshots are captured
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct CaptureOptions {
+    include_cursor: bool,
+    last_region: Option<StoredRegion>,
+    presets: Vec<CapturePreset>,
+    /// The options are never written to the config file
+    #[serde(skip)]
+    in_memory: bool,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        CaptureOptions {
+            include_cursor: false,
+            last_region: None,
+            presets: CapturePreset::defaults(),
+            in_memory: false,
+        }
+    }
+}
+
+/// Data structure for represent a named preset of the selection:
+/// if `fixed_size` is set the selection is exactly `width` x `height` pixels,
+/// otherwise it has the ratio `width:height`
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, Data, Lens)]
+pub struct CapturePreset {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub fixed_size: bool,
+}
+
+impl CapturePreset {
+    pub fn new(name: &str, width: u32, height: u32, fixed_size: bool) -> Self {
+        CapturePreset {
+            name: name.to_string(),
+            width,
+            height,
+            fixed_size,
+        }
+    }
+
+    /// This function return the presets available before the user edits them
+    ///
+    /// Default presets are:
+    /// - 1280x720 and 1920x1080 with fixed size
+    /// - 16:9 and 4:3 ratios
+    pub fn defaults() -> Vec<CapturePreset> {
+        vec![
+            CapturePreset::new("720p", 1280, 720, true),
+            CapturePreset::new("1080p", 1920, 1080, true),
+            CapturePreset::new("16:9", 16, 9, false),
+            CapturePreset::new("4:3", 4, 3, false),
+        ]
+    }
+}
+
+/// Data structure for represent the last region cropped
+/// from a screen, in pixels of the captured screen
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct StoredRegion {
+    /// The region has been cropped from the capture of all the screens
+    pub all_screens: bool,
+    pub screen_index: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Data for CaptureOptions {
+    fn same(&self, other: &Self) -> bool {
+        return self == other;
+    }
+}
+
+impl CaptureOptions {
+    /// This function write the options to the config file,
+    /// the location of config file is `./conf` in `project` folder
+    fn write_toml(&self) {
+        if self.in_memory {
+            return;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(CONF_CAPTURE_FILE_PATH)
+            .expect("Unable to open capture_conf file");
+
+        let toml_string = toml::to_string(&self).expect("Unable to encode data to toml format");
+
+        file.write(toml_string.as_bytes())
+            .expect("Could not write to capture_conf file");
+
+        file.flush().expect("Could not write to capture_conf file");
+    }
+
+    /// This function is use to retrive the options from
+    /// config file, the missing options get the default value
+    fn from_toml() -> Self {
+        let contents = fs::read_to_string(CONF_CAPTURE_FILE_PATH)
+            .expect("Could not read capture_conf file");
+
+        match toml::from_str(&contents) {
+            Ok(options) => options,
+            Err(_) => {
+                let options = CaptureOptions::default();
+                options.write_toml();
+                options
+            }
+        }
+    }
+
+    pub fn new() -> Self {
+        let read_dir = match read_dir(CONF_DIR_PATH) {
+            Ok(r) => r,
+            Err(_) => {
+                create_dir(CONF_DIR_PATH).expect("Unable to create conf dir");
+                read_dir(CONF_DIR_PATH).expect("Unable to read conf dir")
+            }
+        };
+
+        let mut found = false;
+        for e in read_dir {
+            if e.unwrap().file_name() == CONF_CAPTURE_FILE_NAME {
+                found = true;
+                break;
+            }
+        }
+
+        if !found {
+            CaptureOptions::default().write_toml();
+        }
+
+        return CaptureOptions::from_toml();
+    }
+
+    /// This function return the default options, that are kept
+    /// in memory without being written to the config file
+    pub fn in_memory() -> Self {
+        return CaptureOptions {
+            in_memory: true,
+            ..CaptureOptions::default()
+        };
+    }
+
+    /// This function return true if the mouse pointer
+    /// has to be drawn on the screenshots
+    pub fn get_include_cursor(&self) -> bool {
+        return self.include_cursor;
+    }
+
+    /// This function update the preference about the mouse pointer
+    pub fn set_include_cursor(&mut self, include_cursor: bool) {
+        self.include_cursor = include_cursor;
+        self.write_toml();
+    }
+
+    /// This function return the last region cropped from a screen, if any
+    pub fn get_last_region(&self) -> Option<StoredRegion> {
+        return self.last_region.clone();
+    }
+
+    /// This function update the last region cropped from a screen
+    pub fn set_last_region(&mut self, last_region: StoredRegion) {
+        self.last_region = Some(last_region);
+        self.write_toml();
+    }
+
+    /// This function return the presets of the selection
+    pub fn get_presets(&self) -> Vec<CapturePreset> {
+        return self.presets.clone();
+    }
+
+    /// This function update the presets of the selection
+    pub fn set_presets(&mut self, presets: Vec<CapturePreset>) {
+        self.presets = presets;
+        self.write_toml();
+    }
+}
+
+/// This data type is used to serialize and deserialize
+/// data to/from file to save the colours recently used
+/// for drawing, in RGBA format
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ColorOptions {
+    recent_colors: Vec<u32>,
+}
+
+impl Data for ColorOptions {
+    fn same(&self, other: &Self) -> bool {
+        return self == other;
+    }
+}
+
+impl ColorOptions {
+    /// This function write the options to the config file,
+    /// the location of config file is `./conf` in `project` folder
+    fn write_toml(&self) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(CONF_COLOR_FILE_PATH)
+            .expect("Unable to open color_conf file");
+
+        let toml_string = toml::to_string(&self).expect("Unable to encode data to toml format");
+
+        file.write(toml_string.as_bytes())
+            .expect("Could not write to color_conf file");
+
+        file.flush().expect("Could not write to color_conf file");
+    }
+
+    /// This function is use to retrive the options from
+    /// config file, the missing options get the default value
+    fn from_toml() -> Self {
+        let contents =
+            fs::read_to_string(CONF_COLOR_FILE_PATH).expect("Could not read color_conf file");
+
+        match toml::from_str(&contents) {
+            Ok(options) => options,
+            Err(_) => {
+                let options = ColorOptions::default();
+                options.write_toml();
+                options
+            }
+        }
+    }
+
+    pub fn new() -> Self {
+        let read_dir = match read_dir(CONF_DIR_PATH) {
+            Ok(r) => r,
+            Err(_) => {
+                create_dir(CONF_DIR_PATH).expect("Unable to create conf dir");
+                read_dir(CONF_DIR_PATH).expect("Unable to read conf dir")
+            }
+        };
+
+        let mut found = false;
+        for e in read_dir {
+            if e.unwrap().file_name() == CONF_COLOR_FILE_NAME {
+                found = true;
+                break;
+            }
+        }
+
+        if !found {
+            ColorOptions::default().write_toml();
+        }
+
+        return ColorOptions::from_toml();
+    }
+
+    /// This function return the colours recently used, the last one first
+    pub fn get_recent_colors(&self) -> Vec<u32> {
+        return self.recent_colors.clone();
+    }
+
+    /// This function move `color` at the beginning of the recent colours,
+    /// the oldest one is forgotten when there are too many
+    pub fn add_recent_color(&mut self, color: u32) {
+        if self.recent_colors.first() == Some(&color) {
+            return;
+        }
+
+        self.recent_colors.retain(|c| *c != color);
+        self.recent_colors.insert(0, color);
+        self.recent_colors.truncate(RECENT_COLORS_LIMIT);
+        self.write_toml();
+    }
+}
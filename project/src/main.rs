@@ -1,26 +1,40 @@
 use druid::{AppLauncher, LocalizedString, Size, WindowDesc};
 use event_lib::*;
 use gui_lib::*;
-use screenshot_lib::screen_size;
+use screenshot_lib::{primary_screen, source::source::ScreenshotsSource};
+use std::sync::Arc;
 
 const WINDOW_TITLE: LocalizedString<AppState> = LocalizedString::new("Screenshot App");
 const WINDOW_MIN_SIZE: Size = Size::new(1100.0, 700.0);
 
 fn main() {
-    let initial_state = AppState::new();
-    let screen_size = screen_size();
+    let capture_source = Arc::new(ScreenshotsSource::new());
+    let initial_state = AppState::with_capture_source(capture_source.clone());
+    // centre the window on the primary screen, that is not always the first one
+    let (screen_x, screen_y, screen_size) = match primary_screen(capture_source.as_ref()) {
+        Ok(screen) => (
+            screen.x as f64,
+            screen.y as f64,
+            Size::new(screen.width as f64, screen.height as f64),
+        ),
+        Err(_) => (0.0, 0.0, WINDOW_MIN_SIZE),
+    };
 
     let main_window = WindowDesc::new(build_root_widget())
         .title(WINDOW_TITLE)
         .menu(|winid, data, _| build_menu(winid, data))
         .window_size(WINDOW_MIN_SIZE)
         .set_position((
-            screen_size.width / 2.0 - WINDOW_MIN_SIZE.width / 2.0,
-            screen_size.height / 2.0 - WINDOW_MIN_SIZE.height / 2.0,
+            screen_x + screen_size.width / 2.0 - WINDOW_MIN_SIZE.width / 2.0,
+            screen_y + screen_size.height / 2.0 - WINDOW_MIN_SIZE.height / 2.0,
         ))
         .with_min_size(WINDOW_MIN_SIZE);
 
-    AppLauncher::with_window(main_window)
+    let launcher = AppLauncher::with_window(main_window);
+
+    watch_screens(capture_source.clone(), launcher.get_external_handle());
+
+    launcher
         .delegate(EventHandler::new())
         .launch(initial_state)
         .expect("Failed to launch application");